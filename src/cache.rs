@@ -0,0 +1,283 @@
+// Cache of finished searches, so that repeated queries don't hit every engine again.
+// The storage is pluggable: results can be kept in memory or on disk,
+// where they survive restarts.
+pub mod cache {
+    use std::{
+        collections::BTreeMap,
+        error::Error,
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use ahash::AHashMap;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use tokio::task;
+
+    use crate::{engines::engine_base::engine_base::SearchResult, utils::utils::hash_string};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CachedSearch {
+        pub created_at: DateTime<Utc>,
+        pub results: Vec<SearchResult>,
+    }
+
+    /// Storage of a `ResultCache`.
+    ///
+    /// Backends only need to bound their size; expiry is handled by the `ResultCache`.
+    /// They are called on the blocking thread pool, so they may do blocking I/O.
+    pub trait CacheBackend: Send + Sync {
+        fn get(&self, key: &str) -> Option<CachedSearch>;
+
+        fn insert(&self, key: &str, search: CachedSearch);
+    }
+
+    #[derive(Default)]
+    struct LruEntries {
+        // Value and the tick of the last access
+        entries: AHashMap<String, (CachedSearch, u64)>,
+        // Keys by the tick of their last access, least recently used first
+        accesses: BTreeMap<u64, String>,
+        tick: u64,
+    }
+
+    impl LruEntries {
+        /// Mark `key` as used just now
+        fn touch(&mut self, key: &str) -> Option<&CachedSearch> {
+            self.tick += 1;
+
+            let (search, last_access) = self.entries.get_mut(key)?;
+            let key = self
+                .accesses
+                .remove(last_access)
+                .expect("every entry has an access");
+            *last_access = self.tick;
+            self.accesses.insert(self.tick, key);
+
+            Some(search)
+        }
+    }
+
+    /// Least recently used cache in memory
+    pub struct MemoryCache {
+        max_entries: usize,
+        entries: Mutex<LruEntries>,
+    }
+
+    impl MemoryCache {
+        pub fn new(max_entries: usize) -> Self {
+            Self {
+                max_entries,
+                entries: Mutex::new(LruEntries::default()),
+            }
+        }
+    }
+
+    impl CacheBackend for MemoryCache {
+        fn get(&self, key: &str) -> Option<CachedSearch> {
+            self.entries.lock().unwrap().touch(key).cloned()
+        }
+
+        fn insert(&self, key: &str, search: CachedSearch) {
+            let mut entries = self.entries.lock().unwrap();
+            let entries = &mut *entries;
+
+            if let Some((existing, _)) = entries.entries.get_mut(key) {
+                *existing = search;
+                entries.touch(key);
+
+                return;
+            }
+
+            entries.tick += 1;
+            entries
+                .entries
+                .insert(key.to_string(), (search, entries.tick));
+            entries.accesses.insert(entries.tick, key.to_string());
+
+            while entries.entries.len() > self.max_entries {
+                let Some((_, key)) = entries.accesses.pop_first() else {
+                    break;
+                };
+
+                entries.entries.remove(&key);
+            }
+        }
+    }
+
+    // Only files starting with it are evicted, the directory may be shared with other files
+    const DISK_ENTRY_PREFIX: &str = "tifsep-cache-";
+
+    /// Cache stored as one JSON file per search in a directory
+    pub struct DiskCache {
+        directory: PathBuf,
+        max_entries: usize,
+    }
+
+    impl DiskCache {
+        pub fn new(directory: PathBuf, max_entries: usize) -> Result<Self, Box<dyn Error>> {
+            fs::create_dir_all(&directory)?;
+
+            Ok(Self {
+                directory,
+                max_entries,
+            })
+        }
+
+        fn path(&self, key: &str) -> PathBuf {
+            self.directory
+                .join(format!("{}{:X}.json", DISK_ENTRY_PREFIX, hash_string(key)))
+        }
+
+        /// Delete the oldest entries until at most `max_entries` are left
+        fn evict(&self) -> Result<(), Box<dyn Error>> {
+            let mut files = fs::read_dir(&self.directory)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+
+                    name.starts_with(DISK_ENTRY_PREFIX) && name.ends_with(".json")
+                })
+                .map(|entry| {
+                    let modified = entry
+                        .metadata()
+                        .and_then(|metadata| metadata.modified())
+                        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+                    (modified, entry.path())
+                })
+                .collect::<Vec<_>>();
+
+            if files.len() <= self.max_entries {
+                return Ok(());
+            }
+
+            files.sort();
+
+            for (_, path) in &files[..files.len() - self.max_entries] {
+                fs::remove_file(path)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct DiskEntry {
+        // Stored to tell apart keys with the same hash
+        key: String,
+        search: CachedSearch,
+    }
+
+    impl CacheBackend for DiskCache {
+        fn get(&self, key: &str) -> Option<CachedSearch> {
+            let contents = fs::read_to_string(self.path(key)).ok()?;
+            let entry: DiskEntry = serde_json::from_str(&contents).ok()?;
+
+            if entry.key != key {
+                return None;
+            }
+
+            Some(entry.search)
+        }
+
+        fn insert(&self, key: &str, search: CachedSearch) {
+            let entry = DiskEntry {
+                key: key.to_string(),
+                search,
+            };
+
+            // The cache is only an optimization, so failing to write it is not an error
+            if let Ok(contents) = serde_json::to_string(&entry) {
+                if fs::write(self.path(key), contents).is_ok() {
+                    let _ = self.evict();
+                }
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum CacheBackendKind {
+        None,
+        Memory,
+        Disk,
+    }
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(default)]
+    pub struct CacheConfig {
+        pub backend: CacheBackendKind,
+        pub ttl_secs: u64,
+        pub max_entries: usize,
+        /// Directory of the disk backend
+        pub directory: PathBuf,
+    }
+
+    impl Default for CacheConfig {
+        fn default() -> Self {
+            Self {
+                backend: CacheBackendKind::Memory,
+                ttl_secs: 10 * 60,
+                max_entries: 256,
+                directory: PathBuf::from("./cache"),
+            }
+        }
+    }
+
+    pub struct ResultCache {
+        backend: Option<Arc<dyn CacheBackend>>,
+        ttl: Duration,
+    }
+
+    impl ResultCache {
+        pub fn new(backend: Option<Arc<dyn CacheBackend>>, ttl: Duration) -> Self {
+            Self { backend, ttl }
+        }
+
+        pub fn from_config(config: &CacheConfig) -> Result<Self, Box<dyn Error>> {
+            let backend: Option<Arc<dyn CacheBackend>> = match config.backend {
+                CacheBackendKind::None => None,
+                CacheBackendKind::Memory => Some(Arc::new(MemoryCache::new(config.max_entries))),
+                CacheBackendKind::Disk => Some(Arc::new(DiskCache::new(
+                    config.directory.clone(),
+                    config.max_entries,
+                )?)),
+            };
+
+            Ok(Self::new(backend, Duration::from_secs(config.ttl_secs)))
+        }
+
+        /// Cached search for `key`, if there is one that hasn't expired yet
+        pub async fn get(&self, key: &str) -> Option<CachedSearch> {
+            let backend = self.backend.clone()?;
+            let key = key.to_string();
+            let search = task::spawn_blocking(move || backend.get(&key))
+                .await
+                .ok()??;
+            let age = Utc::now().signed_duration_since(search.created_at);
+
+            if age.to_std().unwrap_or(Duration::ZERO) > self.ttl {
+                return None;
+            }
+
+            Some(search)
+        }
+
+        pub async fn insert(&self, key: &str, results: Vec<SearchResult>) {
+            let Some(backend) = self.backend.clone() else {
+                return;
+            };
+            let key = key.to_string();
+            let search = CachedSearch {
+                created_at: Utc::now(),
+                results,
+            };
+
+            let _ = task::spawn_blocking(move || backend.insert(&key, search)).await;
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Tests of the result cache and its backends
+pub mod cache_tests {
+    use std::{fs, sync::Arc, time::Duration};
+
+    use chrono::Utc;
+
+    use crate::{
+        cache::cache::{CacheBackend, CachedSearch, DiskCache, MemoryCache, ResultCache},
+        engines::{
+            engine_base::engine_base::SearchEngine,
+            test_fixtures::test_fixtures::{search_result, temporary_directory},
+        },
+    };
+
+    fn search(url: &str) -> CachedSearch {
+        CachedSearch {
+            created_at: Utc::now(),
+            results: vec![search_result(SearchEngine::Brave, url, 1)],
+        }
+    }
+
+    fn url(search: Option<CachedSearch>) -> Option<String> {
+        Some(search?.results.first()?.url.clone())
+    }
+
+    #[test]
+    fn memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2);
+
+        cache.insert("a", search("https://a.com/"));
+        cache.insert("b", search("https://b.com/"));
+        // Reading "a" makes "b" the least recently used
+        assert!(cache.get("a").is_some());
+        cache.insert("c", search("https://c.com/"));
+
+        assert_eq!(url(cache.get("a")), Some("https://a.com/".to_string()));
+        assert!(cache.get("b").is_none());
+        assert_eq!(url(cache.get("c")), Some("https://c.com/".to_string()));
+    }
+
+    #[test]
+    fn memory_cache_replaces_entries() {
+        let cache = MemoryCache::new(2);
+
+        cache.insert("a", search("https://a.com/"));
+        cache.insert("b", search("https://b.com/"));
+        cache.insert("a", search("https://a.com/new"));
+        cache.insert("c", search("https://c.com/"));
+
+        // Replacing "a" counted as using it
+        assert_eq!(url(cache.get("a")), Some("https://a.com/new".to_string()));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[tokio::test]
+    async fn expired_searches_are_ignored() {
+        let backend = Arc::new(MemoryCache::new(8));
+        let cache = ResultCache::new(Some(backend.clone()), Duration::from_secs(60));
+
+        cache
+            .insert(
+                "fresh",
+                vec![search_result(SearchEngine::Brave, "https://a.com/", 1)],
+            )
+            .await;
+        backend.insert(
+            "expired",
+            CachedSearch {
+                created_at: Utc::now() - chrono::Duration::seconds(61),
+                results: vec![search_result(SearchEngine::Brave, "https://b.com/", 1)],
+            },
+        );
+
+        assert_eq!(
+            url(cache.get("fresh").await),
+            Some("https://a.com/".to_string())
+        );
+        assert!(cache.get("expired").await.is_none());
+        assert!(cache.get("missing").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn disabled_cache_stores_nothing() {
+        let cache = ResultCache::new(None, Duration::from_secs(60));
+
+        cache
+            .insert(
+                "a",
+                vec![search_result(SearchEngine::Brave, "https://a.com/", 1)],
+            )
+            .await;
+
+        assert!(cache.get("a").await.is_none());
+    }
+
+    #[test]
+    fn disk_cache_survives_restarts() {
+        let directory = temporary_directory("round-trip");
+        let cached = search("https://a.com/");
+
+        DiskCache::new(directory.clone(), 8)
+            .unwrap()
+            .insert("a", cached.clone());
+
+        let cache = DiskCache::new(directory.clone(), 8).unwrap();
+        let restored = cache.get("a").unwrap();
+
+        assert_eq!(restored.created_at, cached.created_at);
+        assert_eq!(restored.results, cached.results);
+        assert!(cache.get("b").is_none());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn disk_cache_evicts_old_files() {
+        let directory = temporary_directory("eviction");
+        let cache = DiskCache::new(directory.clone(), 2).unwrap();
+        // Other files in the directory aren't entries of the cache
+        let unrelated = directory.join("unrelated.json");
+        fs::write(&unrelated, "{}").unwrap();
+
+        for key in ["a", "b", "c", "d"] {
+            cache.insert(key, search("https://a.com/"));
+        }
+
+        let files = fs::read_dir(&directory).unwrap().count();
+        assert_eq!(files, 3);
+        assert!(unrelated.exists());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
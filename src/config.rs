@@ -0,0 +1,180 @@
+// Configuration of tifsep, read from `./tifsep.toml` or the file set in `TIFSEP_CONFIG`.
+// Every section is optional; engines are defined as described in `configurable.rs`.
+//
+// Example configuration:
+//
+// query_timeout_ms = 5000
+// default_engine_timeout_ms = 3000
+//
+// [engine_timeouts]
+// Bing = 2000
+//
+// [default_rate_limit]
+// requests_per_second = 5.0
+// max_concurrency = 8
+// max_wait_ms = 1000
+//
+// [rate_limits.Bing]
+// requests_per_second = 1.0
+//
+// [cache]
+// backend = "disk"
+// ttl_secs = 600
+// max_entries = 256
+// directory = "./cache"
+//
+// [recording]
+// mode = "record"
+// directory = "./fixtures"
+//
+// [drift]
+// window = 20
+// min_responses = 5
+// broken_rate = 0.5
+// directory = "./diagnostics"
+// max_dumps = 50
+//
+// [ranking]
+// k = 60
+//
+// [ranking.weights]
+// Bing = 0.5
+// Mojeek = 1.5
+//
+// [url_cleaning]
+// clearurls_rules = "./clearurls.json"
+// parameters = ["ref_id", "campaign_*"]
+// exceptions = ["example.com"]
+//
+// [[engine]]
+// name = "Mojeek"
+// ...
+pub mod config {
+    use std::{collections::BTreeMap, env, error::Error, fs, path::PathBuf, time::Duration};
+
+    use serde::Deserialize;
+
+    use crate::{
+        cache::cache::CacheConfig,
+        engines::{
+            configurable::configurable::{ConfigurableEngine, EngineDefinition},
+            drift::drift::DriftConfig,
+            engine_base::engine_base::SearchEngine,
+            rate_limit::rate_limit::RateLimit,
+        },
+        ranking::ranking::RankingConfig,
+        recording::recording::RecordingConfig,
+        tracking::tracking::TrackingConfig,
+    };
+
+    const CONFIG_ENV: &str = "TIFSEP_CONFIG";
+    const DEFAULT_CONFIG_PATH: &str = "./tifsep.toml";
+    // The config used to only define engines, these are still read if no config is set
+    const LEGACY_CONFIG_ENV: &str = "TIFSEP_ENGINES_CONFIG";
+    const LEGACY_CONFIG_PATH: &str = "./engines.toml";
+
+    #[derive(Debug, Deserialize)]
+    struct RawConfig {
+        query_timeout_ms: Option<u64>,
+        default_engine_timeout_ms: Option<u64>,
+        #[serde(default)]
+        engine_timeouts: BTreeMap<String, u64>,
+        default_rate_limit: Option<RateLimit>,
+        #[serde(default)]
+        rate_limits: BTreeMap<String, RateLimit>,
+        #[serde(default)]
+        cache: CacheConfig,
+        #[serde(default)]
+        recording: RecordingConfig,
+        #[serde(default)]
+        drift: DriftConfig,
+        #[serde(default)]
+        ranking: RankingConfig,
+        #[serde(default)]
+        url_cleaning: TrackingConfig,
+        #[serde(default)]
+        engine: Vec<EngineDefinition>,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct AppConfig {
+        /// Engines defined in the config, in addition to or replacing the built-in ones
+        pub engines: Vec<ConfigurableEngine>,
+        /// Time after which the whole page is closed, regardless of unfinished engines
+        pub query_timeout: Option<Duration>,
+        pub default_engine_timeout: Option<Duration>,
+        pub engine_timeouts: Vec<(SearchEngine, Duration)>,
+        pub default_rate_limit: Option<RateLimit>,
+        pub rate_limits: Vec<(SearchEngine, RateLimit)>,
+        pub cache: CacheConfig,
+        pub recording: RecordingConfig,
+        pub drift: DriftConfig,
+        pub ranking: RankingConfig,
+        pub url_cleaning: TrackingConfig,
+    }
+
+    pub fn parse_config(contents: &str) -> Result<AppConfig, Box<dyn Error>> {
+        let config: RawConfig = toml::from_str(contents)?;
+
+        Ok(AppConfig {
+            engines: config
+                .engine
+                .into_iter()
+                .map(ConfigurableEngine::from_definition)
+                .collect::<Result<_, _>>()?,
+            query_timeout: config.query_timeout_ms.map(Duration::from_millis),
+            default_engine_timeout: config.default_engine_timeout_ms.map(Duration::from_millis),
+            engine_timeouts: config
+                .engine_timeouts
+                .iter()
+                .map(|(name, timeout)| {
+                    (
+                        SearchEngine::from_name(name),
+                        Duration::from_millis(*timeout),
+                    )
+                })
+                .collect(),
+            default_rate_limit: config.default_rate_limit,
+            rate_limits: config
+                .rate_limits
+                .iter()
+                .map(|(name, limit)| (SearchEngine::from_name(name), *limit))
+                .collect(),
+            cache: config.cache,
+            recording: config.recording,
+            drift: config.drift,
+            ranking: config.ranking,
+            url_cleaning: config.url_cleaning,
+        })
+    }
+
+    /// Path of the config file: the one set in `$TIFSEP_CONFIG` or `$TIFSEP_ENGINES_CONFIG`,
+    /// even if it doesn't exist, otherwise `./tifsep.toml` or `./engines.toml` if they exist.
+    fn config_path() -> Option<PathBuf> {
+        if let Some(path) = [CONFIG_ENV, LEGACY_CONFIG_ENV]
+            .iter()
+            .find_map(|variable| env::var(variable).ok())
+        {
+            return Some(PathBuf::from(path));
+        }
+
+        [DEFAULT_CONFIG_PATH, LEGACY_CONFIG_PATH]
+            .iter()
+            .map(PathBuf::from)
+            .find(|path| path.exists())
+    }
+
+    /// Load the config file, see `config_path`.
+    /// Returns the default config if no config file is set or exists.
+    pub fn load_config() -> Result<AppConfig, Box<dyn Error>> {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Ok(AppConfig::default()),
+        };
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+
+        parse_config(&contents)
+    }
+}
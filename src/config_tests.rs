@@ -0,0 +1,243 @@
+// Tests of parsing the config file and the engines defined in it
+pub mod config_tests {
+    use std::{env, time::Duration};
+
+    use crate::{
+        cache::cache::CacheBackendKind,
+        config::config::{load_config, parse_config},
+        engines::{
+            engine_base::engine_base::SearchEngine, registry::registry::Engine,
+            test_fixtures::test_fixtures::temporary_directory,
+        },
+    };
+
+    const REGEX_EXTRACTION: &str = r#"
+        results_start = '<ul class="results">'
+        single_result = '<a href="(?P<url>.+?)">(?P<title>.+?)</a><p>(?P<description>.+?)</p>'
+    "#;
+
+    const SELECTOR_EXTRACTION: &str = r#"
+        [engine.selectors]
+        result = "ul.results > li"
+        title = "a"
+        url = "a@href"
+        description = "p"
+    "#;
+
+    /// Config defining a single engine named "Example", followed by `fields`
+    fn engine_config(fields: &str) -> String {
+        format!(
+            "[[engine]]\nname = \"Example\"\nurl = \"https://example.com/search\"\n{}",
+            fields
+        )
+    }
+
+    fn error(config: &str) -> String {
+        parse_config(config).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn empty_config() {
+        let config = parse_config("").unwrap();
+
+        assert!(config.engines.is_empty());
+        assert_eq!(config.query_timeout, None);
+        assert_eq!(config.default_engine_timeout, None);
+        assert!(matches!(config.cache.backend, CacheBackendKind::Memory));
+        assert_eq!(config.ranking.k, 60.0);
+    }
+
+    #[test]
+    fn global_sections() {
+        let config = parse_config(
+            r#"
+            query_timeout_ms = 5000
+            default_engine_timeout_ms = 3000
+
+            [engine_timeouts]
+            Bing = 2000
+
+            [rate_limits.Bing]
+            requests_per_second = 1.0
+
+            [cache]
+            backend = "none"
+
+            [ranking.weights]
+            Bing = 0.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.query_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.default_engine_timeout, Some(Duration::from_secs(3)));
+        assert_eq!(
+            config.engine_timeouts,
+            vec![(SearchEngine::Bing, Duration::from_secs(2))]
+        );
+        assert_eq!(config.rate_limits.len(), 1);
+        assert_eq!(config.rate_limits[0].0, SearchEngine::Bing);
+        assert!(matches!(config.cache.backend, CacheBackendKind::None));
+        assert_eq!(config.ranking.weights.get("Bing"), Some(&0.5));
+
+        assert!(parse_config("[cache]\nbackend = \"redis\"").is_err());
+        assert!(parse_config("query_timeout_ms = \"5s\"").is_err());
+    }
+
+    #[test]
+    fn engine_definitions() {
+        let config = parse_config(&format!(
+            "{}\n{}",
+            engine_config(&format!("method = \"post\"\n{}", REGEX_EXTRACTION)),
+            engine_config(SELECTOR_EXTRACTION).replace("Example", "Other"),
+        ))
+        .unwrap();
+        let kinds = config
+            .engines
+            .iter()
+            .map(|engine| engine.kind())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                SearchEngine::from_name("Example"),
+                SearchEngine::from_name("Other")
+            ]
+        );
+    }
+
+    #[test]
+    fn engines_with_builtin_names_replace_them() {
+        let config =
+            parse_config(&engine_config(REGEX_EXTRACTION).replace("Example", "Bing")).unwrap();
+
+        assert_eq!(config.engines[0].kind(), SearchEngine::Bing);
+    }
+
+    #[test]
+    fn unsupported_methods() {
+        let message = error(&engine_config(&format!(
+            "method = \"PUT\"\n{}",
+            REGEX_EXTRACTION
+        )));
+
+        assert!(message.contains("Engine Example"), "{}", message);
+        assert!(message.contains("PUT"), "{}", message);
+
+        assert!(parse_config(&engine_config(&format!(
+            "method = \"NOT A METHOD\"\n{}",
+            REGEX_EXTRACTION
+        )))
+        .is_err());
+    }
+
+    #[test]
+    fn unknown_date_locales() {
+        let message = error(&engine_config(&format!(
+            "date_locale = \"xx\"\n{}",
+            REGEX_EXTRACTION
+        )));
+
+        assert!(message.contains("unknown date locale xx"), "{}", message);
+        assert!(message.contains("en"), "{}", message);
+    }
+
+    #[test]
+    fn exactly_one_extraction_mode() {
+        let both = format!(
+            "{}\n{}",
+            engine_config(REGEX_EXTRACTION),
+            SELECTOR_EXTRACTION
+        );
+        let neither = engine_config("");
+        let only_start = engine_config("results_start = '<ul>'");
+
+        for config in [both, neither, only_start] {
+            let message = error(&config);
+
+            assert!(
+                message.contains("either `selectors` or both"),
+                "{}: {}",
+                config,
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        let invalid_regex = engine_config(
+            r#"
+            results_start = '<ul>'
+            single_result = '(?P<url>.+?'
+            "#,
+        );
+        let invalid_block_page =
+            engine_config(&format!("block_page = '(captcha'\n{}", REGEX_EXTRACTION));
+        let invalid_selector =
+            engine_config(&SELECTOR_EXTRACTION.replace("ul.results > li", "ul >"));
+
+        for config in [invalid_regex, invalid_block_page, invalid_selector] {
+            assert!(parse_config(&config).is_err(), "{}", config);
+        }
+    }
+
+    #[test]
+    fn redirects_of_configured_engines() {
+        let redirects = r#"
+            [[engine.redirects]]
+            host = "example.com"
+            path = "/redirect"
+            parameter = "url"
+        "#;
+        let response = concat!(
+            r#"<ul class="results"><li>"#,
+            r#"<a href="/redirect?url=https%3A%2F%2Fdestination.com%2Fpage">Title</a>"#,
+            r#"<p>Description</p></li></ul>"#,
+        );
+
+        for extraction in [REGEX_EXTRACTION, SELECTOR_EXTRACTION] {
+            // Tables have to come after the keys of the engine
+            let config =
+                parse_config(&engine_config(&format!("{}\n{}", extraction, redirects))).unwrap();
+            let mut parser = config.engines[0].parser();
+            parser.push_packet(response.as_bytes());
+
+            assert_eq!(
+                parser.parse_next().map(|result| result.url).as_deref(),
+                Some("https://destination.com/page"),
+                "{}",
+                extraction
+            );
+        }
+    }
+
+    #[test]
+    fn redirects_need_one_destination() {
+        for destination in ["", "parameter = \"url\"\nbing_click = true"] {
+            let message = error(&engine_config(&format!(
+                "{}\n[[engine.redirects]]\nhost = \"example.com\"\npath = \"/r\"\n{}",
+                REGEX_EXTRACTION, destination
+            )));
+
+            assert!(
+                message.contains("redirect example.com/r needs either"),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn missing_config_set_explicitly() {
+        // No other test loads the config from the environment
+        let path = temporary_directory("config").join("tifsep.toml");
+        env::set_var("TIFSEP_CONFIG", &path);
+        let loaded = load_config();
+        env::remove_var("TIFSEP_CONFIG");
+
+        let message = loaded.unwrap_err().to_string();
+        assert!(message.contains("tifsep.toml"), "{}", message);
+    }
+}
@@ -1,4 +1,30 @@
 pub mod bing;
 pub mod brave;
+pub mod configurable;
+pub mod cooldown;
+#[cfg(test)]
+pub mod date_tests;
+pub mod dates;
+pub mod drift;
 pub mod duckduckgo;
 pub mod engine_base;
+pub mod extras;
+#[cfg(test)]
+pub mod extras_tests;
+pub mod html_tokenizer;
+#[cfg(test)]
+pub mod parser_benchmarks;
+#[cfg(test)]
+pub mod parser_fuzzing;
+#[cfg(test)]
+pub mod parser_replay;
+pub mod rate_limit;
+#[cfg(test)]
+pub mod redirect_tests;
+pub mod redirects;
+pub mod registry;
+#[cfg(test)]
+pub mod request_tests;
+pub mod selectors;
+#[cfg(test)]
+pub mod test_fixtures;
@@ -1,58 +1,113 @@
-// Search engine parser for Brave Search
+// Search engine parser for Bing
 // This uses the clearnet, unlocalized version of the search engine.
 pub mod bing {
+    use std::sync::Arc;
+
     use lazy_static::lazy_static;
     use regex::Regex;
-    use tokio::sync::mpsc::Sender;
+    use reqwest::{Client, RequestBuilder};
 
-    use crate::{
-        engines::engine_base::engine_base::{
-            EngineBase, EnginePositions, SearchEngine, SearchResult,
-        },
-        helpers::helpers::build_default_client,
+    use crate::engines::{
+        dates::dates::{DateParser, ENGLISH},
+        engine_base::engine_base::{EngineBase, SearchEngine, SearchResult},
+        redirects::redirects::{Redirect, RedirectKind, UrlResolver},
+        registry::registry::Engine,
+        selectors::selectors::{ResultSelectors, SelectorMap, SelectorResultParser},
     };
 
     lazy_static! {
-        static ref RESULTS_START: Regex = Regex::new(r#"id="b_results""#).unwrap();
-        static ref SINGLE_RESULT: Regex = Regex::new(r#"<li class="b_algo".*?siteicon.*?>.*?<img src="(?P<image>.+?)"(?:.*?class="b_attribution".*?u="(?P<cache>.+?)")?.*?<h2.*?><a href="(?P<url>.+?)".*?>(?P<title>.+?)</a></h2>.*?((<div class="b_caption.*?<p.*?)|(<p class="b_lineclamp.*?))><span.*?</span>(?P<description>.*?)</p>.*?</li>"#).unwrap();
+        static ref SELECTORS: Arc<ResultSelectors> = Arc::new(
+            ResultSelectors::from_map(
+                &SelectorMap {
+                    result: "#b_results > li.b_algo".to_string(),
+                    title: "h2 a".to_string(),
+                    url: "h2 a@href".to_string(),
+                    description: ".b_caption p".to_string(),
+                    image: Some(".siteicon img@src".to_string()),
+                    date: None,
+                    breadcrumbs: Some(".b_attribution cite".to_string()),
+                    sitelinks: Some("ul.b_vList a".to_string()),
+                    // Facts such as ratings follow the description
+                    attributes: Some(".b_factrow".to_string()),
+                },
+                DateParser::default(),
+                UrlResolver::new(Some(URL), &REDIRECTS),
+            )
+            .unwrap()
+        );
+        static ref DATE_PARSER: DateParser =
+            DateParser::new(Some(DATE_FORMAT.to_string()), &ENGLISH);
+        // The date is shown in front of the description, e.g. "Mar 3, 2024 · Description"
+        static ref DESCRIPTION_DATE: Regex =
+            Regex::new(r#"^(?P<date>\d+ \w+ ago|\w+ \d{1,2}, \d{4})\s*·\s*"#).unwrap();
+        static ref BLOCK_PAGE: Arc<Regex> = Arc::new(
+            Regex::new(r#"/turing/captcha|id="b_captcha"|/challenge/verify"#).unwrap()
+        );
     }
 
+    const URL: &str = "https://www.bing.com/search";
     const DATE_FORMAT: &str = "%b %d, %Y";
 
+    // Results are sometimes linked through a click tracking redirect
+    static REDIRECTS: [Redirect; 1] = [Redirect {
+        host: "bing.com",
+        path: "/ck/a",
+        kind: RedirectKind::BingClick,
+    }];
+
     #[derive(Clone, Debug)]
     pub struct Bing {
-        positions: EnginePositions,
+        parser: SelectorResultParser,
     }
 
     impl EngineBase for Bing {
-        fn parse_next<'a>(&mut self) -> Option<SearchResult> {
-            self.positions.handle_block_using_default_method(
-                &SINGLE_RESULT,
-                SearchEngine::Bing,
-                Some(DATE_FORMAT),
-            )
+        fn parse_next(&mut self) -> Option<SearchResult> {
+            let mut result = self.parser.parse_next()?;
+
+            if let Some(capture) = DESCRIPTION_DATE.captures(&result.description) {
+                let prefix_length = capture.get(0).map_or(0, |prefix| prefix.end());
+
+                result.date = capture
+                    .name("date")
+                    .and_then(|date| DATE_PARSER.parse(date.as_str()));
+                result.description.drain(..prefix_length);
+            }
+
+            Some(result)
+        }
+
+        fn push_packet(&mut self, packet: &[u8]) {
+            self.parser.push_packet(packet)
         }
 
-        fn push_packet<'a>(&mut self, packet: impl Iterator<Item = &'a u8>) {
-            self.positions
-                .handle_start_check_using_default_method(&RESULTS_START, packet)
+        fn block_page_regex(&self) -> Option<&Regex> {
+            self.parser.block_page_regex()
         }
     }
 
     impl Bing {
         pub fn new() -> Self {
             Self {
-                positions: EnginePositions::new(),
+                parser: SelectorResultParser::new(
+                    SearchEngine::Bing,
+                    SELECTORS.clone(),
+                    Some(BLOCK_PAGE.clone()),
+                ),
             }
         }
+    }
 
-        pub async fn search(&mut self, query: &str, tx: Sender<SearchResult>) -> Result<(), ()> {
-            let client = build_default_client();
-            let request = client
-                .get(format!("https://www.bing.com/search?q={}", query))
-                .send();
+    impl Engine for Bing {
+        fn kind(&self) -> SearchEngine {
+            SearchEngine::Bing
+        }
+
+        fn parser(&self) -> Box<dyn EngineBase> {
+            Box::new(Bing::new())
+        }
 
-            self.handle_request(request, tx).await
+        fn build_request(&self, client: &Client, query: &str) -> RequestBuilder {
+            client.get(URL).query(&[("q", query)])
         }
     }
 }
@@ -1,58 +1,104 @@
 // Search engine parser for Brave Search
 // This uses the clearnet, unlocalized version of the search engine.
 pub mod brave {
+    use std::sync::Arc;
+
     use lazy_static::lazy_static;
     use regex::Regex;
-    use tokio::sync::mpsc::Sender;
+    use reqwest::{Client, RequestBuilder};
 
-    use crate::{
-        engines::engine_base::engine_base::{
-            EngineBase, EnginePositions, SearchEngine, SearchResult,
-        },
-        helpers::helpers::build_default_client,
+    use crate::engines::{
+        dates::dates::{DateParser, ENGLISH},
+        engine_base::engine_base::{EngineBase, SearchEngine, SearchResult},
+        redirects::redirects::UrlResolver,
+        registry::registry::Engine,
+        selectors::selectors::{ResultSelectors, SelectorMap, SelectorResultParser},
     };
 
     lazy_static! {
-        static ref RESULTS_START: Regex = Regex::new(r#"<body"#).unwrap();
-        static ref SINGLE_RESULT: Regex = Regex::new(r#"<div class="snippet svelte-.+?<a href=.(?P<url>.+?)".+?(?:.+?<img.+?src="(?P<image>.+?)")?.+?<div class="title svelte-.+?">(?P<title>.+?)</div></div>.+?<div class="snippet-description.+?">(?:(?P<date>.+?) - )?(?P<description>.+?)</div>.*?</div>.*?</div>"#).unwrap();
+        static ref SELECTORS: Arc<ResultSelectors> = Arc::new(
+            ResultSelectors::from_map(
+                &SelectorMap {
+                    result: "div.snippet[data-type]".to_string(),
+                    title: "div.title".to_string(),
+                    url: "a@href".to_string(),
+                    description: ".snippet-description".to_string(),
+                    image: Some("img.favicon@src".to_string()),
+                    date: None,
+                    breadcrumbs: Some(".url-path".to_string()),
+                    sitelinks: Some(".deep-links a.deep-link".to_string()),
+                    attributes: Some(".snippet-attributes".to_string()),
+                },
+                DateParser::default(),
+                UrlResolver::new(Some(URL), &[]),
+            )
+            .unwrap()
+        );
+        static ref DATE_PARSER: DateParser =
+            DateParser::new(Some(DATE_FORMAT.to_string()), &ENGLISH);
+        // The date is shown in front of the description, e.g. "3 days ago - Description"
+        static ref DESCRIPTION_DATE: Regex =
+            Regex::new(r#"^(?P<date>\d+ \w+ ago|\w+ \d{1,2}, \d{4}) - "#).unwrap();
+        static ref BLOCK_PAGE: Arc<Regex> =
+            Arc::new(Regex::new(r#"/search/captcha|class="captcha"#).unwrap());
     }
 
-    const DATE_FORMAT: &str = "%m %d, %Y";
+    const URL: &str = "https://search.brave.com/search";
+    const DATE_FORMAT: &str = "%B %d, %Y";
 
     #[derive(Clone, Debug)]
     pub struct Brave {
-        positions: EnginePositions,
+        parser: SelectorResultParser,
     }
 
     impl EngineBase for Brave {
-        fn parse_next<'a>(&mut self) -> Option<SearchResult> {
-            self.positions.handle_block_using_default_method(
-                &SINGLE_RESULT,
-                SearchEngine::Brave,
-                Some(DATE_FORMAT),
-            )
+        fn parse_next(&mut self) -> Option<SearchResult> {
+            let mut result = self.parser.parse_next()?;
+
+            if let Some(capture) = DESCRIPTION_DATE.captures(&result.description) {
+                let prefix_length = capture.get(0).map_or(0, |prefix| prefix.end());
+
+                result.date = capture
+                    .name("date")
+                    .and_then(|date| DATE_PARSER.parse(date.as_str()));
+                result.description.drain(..prefix_length);
+            }
+
+            Some(result)
         }
 
-        fn push_packet<'a>(&mut self, packet: impl Iterator<Item = &'a u8>) {
-            self.positions
-                .handle_start_check_using_default_method(&RESULTS_START, packet)
+        fn push_packet(&mut self, packet: &[u8]) {
+            self.parser.push_packet(packet)
+        }
+
+        fn block_page_regex(&self) -> Option<&Regex> {
+            self.parser.block_page_regex()
         }
     }
 
     impl Brave {
         pub fn new() -> Self {
             Self {
-                positions: EnginePositions::new(),
+                parser: SelectorResultParser::new(
+                    SearchEngine::Brave,
+                    SELECTORS.clone(),
+                    Some(BLOCK_PAGE.clone()),
+                ),
             }
         }
+    }
+
+    impl Engine for Brave {
+        fn kind(&self) -> SearchEngine {
+            SearchEngine::Brave
+        }
 
-        pub async fn search(&mut self, query: &str, tx: Sender<SearchResult>) -> Result<(), ()> {
-            let client = build_default_client();
-            let request = client
-                .get(format!("https://search.brave.com/search?q={}", query))
-                .send();
+        fn parser(&self) -> Box<dyn EngineBase> {
+            Box::new(Brave::new())
+        }
 
-            self.handle_request(request, tx).await
+        fn build_request(&self, client: &Client, query: &str) -> RequestBuilder {
+            client.get(URL).query(&[("q", query)])
         }
     }
 }
@@ -0,0 +1,311 @@
+// Search engines that are defined declaratively in the config file (see `config.rs`)
+// instead of being compiled into tifsep.
+//
+// Built-in engines can't be changed partially: an engine with the name of a built-in
+// one, e.g. `name = "Bing"`, replaces it completely. This is how a built-in parser
+// is fixed without recompiling.
+//
+// Example definition:
+//
+// [[engine]]
+// name = "Mojeek"
+// url = "https://www.mojeek.com/search"
+// method = "GET"
+// params = { q = "{query}" }
+// results_start = 'class="results-standard"'
+// single_result = '<li.*?<a href="(?P<url>.+?)".*?>(?P<title>.+?)</a>.*?<p class="s">(?P<description>.+?)</p>'
+// date_format = "%d %b %Y"
+// date_locale = "en"
+// block_page = 'id="captcha"'
+//
+// Links that route through a redirect of the engine are replaced by their destination,
+// which is either a query parameter or encoded as in Bing's click tracking links:
+//
+// [[engine.redirects]]
+// host = "mojeek.com"
+// path = "/redirect"
+// parameter = "url"
+//
+// [[engine.redirects]]
+// host = "bing.com"
+// path = "/ck/a"
+// bing_click = true
+//
+// Instead of `results_start` and `single_result`, results can be extracted
+// using selectors (see `selectors.rs`):
+//
+// [[engine]]
+// name = "Mojeek"
+// url = "https://www.mojeek.com/search"
+// params = { q = "{query}" }
+//
+// [engine.selectors]
+// result = "ul.results-standard > li"
+// title = "h2 a"
+// url = "h2 a@href"
+// description = "p.s"
+// date = "span.date"
+// breadcrumbs = "span.path"
+// sitelinks = "ul.sitelinks a"
+// attributes = ".facts"
+pub mod configurable {
+    use std::{collections::BTreeMap, error::Error, sync::Arc};
+
+    use regex::Regex;
+    use reqwest::{Client, Method, RequestBuilder};
+    use serde::Deserialize;
+
+    use crate::engines::{
+        dates::dates::{locale_by_code, DateParser, ENGLISH, LOCALES},
+        engine_base::engine_base::{EngineBase, EnginePositions, SearchEngine, SearchResult},
+        redirects::redirects::{Redirect, RedirectKind, UrlResolver},
+        registry::registry::Engine,
+        selectors::selectors::{ResultSelectors, SelectorMap, SelectorResultParser},
+    };
+
+    const QUERY_PLACEHOLDER: &str = "{query}";
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct EngineDefinition {
+        pub name: String,
+        /// URL of the search page; `{query}` is replaced by the url-encoded query
+        pub url: String,
+        #[serde(default = "default_method")]
+        pub method: String,
+        /// Query parameters for GET requests or form fields for POST requests;
+        /// `{query}` is replaced by the raw query
+        #[serde(default)]
+        pub params: BTreeMap<String, String>,
+        pub results_start: Option<String>,
+        pub single_result: Option<String>,
+        /// Used instead of `results_start` and `single_result`
+        pub selectors: Option<SelectorMap>,
+        pub date_format: Option<String>,
+        /// Language of the dates, `en` by default
+        pub date_locale: Option<String>,
+        /// Pattern that only matches captcha or block pages of the engine
+        pub block_page: Option<String>,
+        /// Redirects that result links are routed through
+        #[serde(default)]
+        pub redirects: Vec<RedirectDefinition>,
+    }
+
+    fn default_method() -> String {
+        "GET".to_string()
+    }
+
+    /// Links to `path` on `host` or one of its subdomains lead to the url in
+    /// `parameter`, or to the one encoded like in Bing's click tracking links
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct RedirectDefinition {
+        pub host: String,
+        pub path: String,
+        pub parameter: Option<String>,
+        #[serde(default)]
+        pub bing_click: bool,
+    }
+
+    impl RedirectDefinition {
+        fn into_redirect(self, engine: &str) -> Result<Redirect, Box<dyn Error>> {
+            // Each definition is only leaked once, like the names of custom engines
+            let leak = |text: String| -> &'static str { Box::leak(text.into_boxed_str()) };
+
+            let kind = match (self.parameter, self.bing_click) {
+                (Some(parameter), false) => RedirectKind::QueryParameter(leak(parameter)),
+                (None, true) => RedirectKind::BingClick,
+                _ => {
+                    return Err(format!(
+                        "Engine {}: redirect {}{} needs either `parameter` or `bing_click = true`",
+                        engine, self.host, self.path
+                    )
+                    .into())
+                }
+            };
+
+            Ok(Redirect {
+                host: leak(self.host),
+                path: leak(self.path),
+                kind,
+            })
+        }
+    }
+
+    /// How results are found in the response
+    #[derive(Clone, Debug)]
+    enum Extraction {
+        Regex {
+            results_start: Arc<Regex>,
+            single_result: Arc<Regex>,
+        },
+        Selectors(Arc<ResultSelectors>),
+    }
+
+    /// A compiled `EngineDefinition` that can be registered in the `EngineRegistry`
+    #[derive(Clone, Debug)]
+    pub struct ConfigurableEngine {
+        kind: SearchEngine,
+        url: String,
+        method: Method,
+        params: Vec<(String, String)>,
+        extraction: Extraction,
+        date_parser: DateParser,
+        // Links are relative to the engine's url
+        url_resolver: UrlResolver,
+        block_page: Option<Arc<Regex>>,
+    }
+
+    impl ConfigurableEngine {
+        pub fn from_definition(definition: EngineDefinition) -> Result<Self, Box<dyn Error>> {
+            let method = Method::from_bytes(definition.method.to_uppercase().as_bytes())?;
+
+            if method != Method::GET && method != Method::POST {
+                return Err(format!(
+                    "Engine {}: only GET and POST are supported, got {}",
+                    definition.name, method
+                )
+                .into());
+            }
+
+            let locale = match &definition.date_locale {
+                Some(code) => locale_by_code(code).ok_or_else(|| {
+                    format!(
+                        "Engine {}: unknown date locale {}, supported are {}",
+                        definition.name,
+                        code,
+                        LOCALES
+                            .iter()
+                            .map(|locale| locale.code)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })?,
+                None => &ENGLISH,
+            };
+            let date_parser = DateParser::new(definition.date_format, locale);
+
+            let redirects = definition
+                .redirects
+                .into_iter()
+                .map(|redirect| redirect.into_redirect(&definition.name))
+                .collect::<Result<Vec<_>, _>>()?;
+            let url_resolver = UrlResolver::new(
+                Some(&definition.url),
+                Box::leak(redirects.into_boxed_slice()),
+            );
+
+            let extraction = match (
+                &definition.selectors,
+                &definition.results_start,
+                &definition.single_result,
+            ) {
+                (Some(selectors), None, None) => Extraction::Selectors(Arc::new(
+                    ResultSelectors::from_map(
+                        selectors,
+                        date_parser.clone(),
+                        url_resolver.clone(),
+                    )?,
+                )),
+                (None, Some(results_start), Some(single_result)) => Extraction::Regex {
+                    results_start: Arc::new(Regex::new(results_start)?),
+                    single_result: Arc::new(Regex::new(single_result)?),
+                },
+                _ => {
+                    return Err(format!(
+                        "Engine {}: either `selectors` or both `results_start` and `single_result` are required",
+                        definition.name
+                    )
+                    .into())
+                }
+            };
+
+            Ok(Self {
+                kind: SearchEngine::from_name(&definition.name),
+                url_resolver,
+                url: definition.url,
+                method,
+                params: definition.params.into_iter().collect(),
+                extraction,
+                date_parser,
+                block_page: match definition.block_page {
+                    Some(block_page) => Some(Arc::new(Regex::new(&block_page)?)),
+                    None => None,
+                },
+            })
+        }
+    }
+
+    impl Engine for ConfigurableEngine {
+        fn kind(&self) -> SearchEngine {
+            self.kind
+        }
+
+        fn parser(&self) -> Box<dyn EngineBase> {
+            match &self.extraction {
+                Extraction::Regex {
+                    results_start,
+                    single_result,
+                } => Box::new(ConfigurableParser {
+                    kind: self.kind,
+                    positions: EnginePositions::new(),
+                    results_start: results_start.clone(),
+                    single_result: single_result.clone(),
+                    date_parser: self.date_parser.clone(),
+                    url_resolver: self.url_resolver.clone(),
+                    block_page: self.block_page.clone(),
+                }),
+                Extraction::Selectors(selectors) => Box::new(SelectorResultParser::new(
+                    self.kind,
+                    selectors.clone(),
+                    self.block_page.clone(),
+                )),
+            }
+        }
+
+        fn build_request(&self, client: &Client, query: &str) -> RequestBuilder {
+            let url = self
+                .url
+                .replace(QUERY_PLACEHOLDER, &urlencoding::encode(query));
+            let params = self
+                .params
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.replace(QUERY_PLACEHOLDER, query)))
+                .collect::<Vec<_>>();
+
+            if self.method == Method::POST {
+                client.post(url).form(&params)
+            } else {
+                client.get(url).query(&params)
+            }
+        }
+    }
+
+    pub struct ConfigurableParser {
+        kind: SearchEngine,
+        positions: EnginePositions,
+        results_start: Arc<Regex>,
+        single_result: Arc<Regex>,
+        date_parser: DateParser,
+        url_resolver: UrlResolver,
+        block_page: Option<Arc<Regex>>,
+    }
+
+    impl EngineBase for ConfigurableParser {
+        fn parse_next(&mut self) -> Option<SearchResult> {
+            self.positions.handle_block_using_default_method(
+                &self.single_result,
+                self.kind,
+                &self.date_parser,
+                &self.url_resolver,
+            )
+        }
+
+        fn push_packet(&mut self, packet: &[u8]) {
+            self.positions
+                .handle_start_check_using_default_method(&self.results_start, packet)
+        }
+
+        fn block_page_regex(&self) -> Option<&Regex> {
+            self.block_page.as_deref()
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Circuit breaker for engines that serve captchas or block pages.
+// A blocked engine is skipped for a while; every consecutive block
+// doubles the time it is skipped.
+pub mod cooldown {
+    use std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    use ahash::AHashMap;
+
+    use crate::engines::engine_base::engine_base::SearchEngine;
+
+    const BASE_COOLDOWN: Duration = Duration::from_secs(60);
+    const MAX_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+    #[derive(Clone, Copy, Debug)]
+    struct CooldownState {
+        consecutive_blocks: u32,
+        until: Instant,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct EngineCooldowns {
+        states: Mutex<AHashMap<SearchEngine, CooldownState>>,
+    }
+
+    impl EngineCooldowns {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Whether the engine should be skipped because it blocked us recently
+        pub fn is_cooling_down(&self, engine: &SearchEngine) -> bool {
+            match self.states.lock().unwrap().get(engine) {
+                Some(state) => Instant::now() < state.until,
+                None => false,
+            }
+        }
+
+        /// Put the engine into cooldown; returns how long it will be skipped
+        pub fn report_blocked(&self, engine: SearchEngine) -> Duration {
+            let mut states = self.states.lock().unwrap();
+            let consecutive_blocks = match states.get(&engine) {
+                Some(state) => state.consecutive_blocks + 1,
+                None => 1,
+            };
+
+            let cooldown = BASE_COOLDOWN
+                .saturating_mul(2u32.saturating_pow(consecutive_blocks - 1))
+                .min(MAX_COOLDOWN);
+
+            states.insert(
+                engine,
+                CooldownState {
+                    consecutive_blocks,
+                    until: Instant::now() + cooldown,
+                },
+            );
+
+            cooldown
+        }
+
+        /// Close the circuit again once the engine answered normally
+        pub fn report_success(&self, engine: &SearchEngine) {
+            self.states.lock().unwrap().remove(engine);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use super::{EngineCooldowns, MAX_COOLDOWN};
+        use crate::engines::engine_base::engine_base::SearchEngine;
+
+        #[test]
+        fn backoff_doubles_up_to_the_cap() {
+            let cooldowns = EngineCooldowns::new();
+            let cooldowns = (0..9)
+                .map(|_| cooldowns.report_blocked(SearchEngine::Bing).as_secs())
+                .collect::<Vec<_>>();
+
+            assert_eq!(cooldowns, [60, 120, 240, 480, 960, 1920, 3600, 3600, 3600]);
+            assert_eq!(MAX_COOLDOWN, Duration::from_secs(60 * 60));
+        }
+
+        #[test]
+        fn engines_cool_down_separately() {
+            let cooldowns = EngineCooldowns::new();
+
+            assert!(!cooldowns.is_cooling_down(&SearchEngine::Bing));
+
+            cooldowns.report_blocked(SearchEngine::Bing);
+            cooldowns.report_blocked(SearchEngine::Bing);
+
+            assert!(cooldowns.is_cooling_down(&SearchEngine::Bing));
+            assert!(!cooldowns.is_cooling_down(&SearchEngine::Brave));
+            assert_eq!(
+                cooldowns.report_blocked(SearchEngine::Brave),
+                Duration::from_secs(60)
+            );
+        }
+
+        #[test]
+        fn success_resets_the_backoff() {
+            let cooldowns = EngineCooldowns::new();
+
+            for _ in 0..4 {
+                cooldowns.report_blocked(SearchEngine::DuckDuckGo);
+            }
+            cooldowns.report_success(&SearchEngine::DuckDuckGo);
+
+            assert!(!cooldowns.is_cooling_down(&SearchEngine::DuckDuckGo));
+            assert_eq!(
+                cooldowns.report_blocked(SearchEngine::DuckDuckGo),
+                Duration::from_secs(60)
+            );
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Tests of the date parsing, using dates as the engines show them
+pub mod date_tests {
+    use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+    use crate::engines::dates::dates::{DateParser, ENGLISH, GERMAN};
+
+    fn now() -> DateTime<Utc> {
+        "2024-03-10T12:00:00Z".parse().unwrap()
+    }
+
+    fn day(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+    }
+
+    fn absolute(parser: &DateParser, date: &str) -> Option<DateTime<Utc>> {
+        let parsed = parser.parse_at(date, now())?;
+        assert!(!parsed.is_relative, "{} is not absolute", date);
+
+        Some(parsed.date)
+    }
+
+    fn relative(parser: &DateParser, date: &str) -> Option<TimeDelta> {
+        let parsed = parser.parse_at(date, now())?;
+        assert!(parsed.is_relative, "{} is not relative", date);
+
+        Some(now() - parsed.date)
+    }
+
+    #[test]
+    fn relative_dates() {
+        let parser = DateParser::default();
+
+        assert_eq!(relative(&parser, "3 days ago"), Some(TimeDelta::days(3)));
+        assert_eq!(relative(&parser, "1 hour ago"), Some(TimeDelta::hours(1)));
+        assert_eq!(relative(&parser, "an hour ago"), Some(TimeDelta::hours(1)));
+        assert_eq!(relative(&parser, "5h ago"), Some(TimeDelta::hours(5)));
+        assert_eq!(
+            relative(&parser, "12 mins ago"),
+            Some(TimeDelta::minutes(12))
+        );
+        assert_eq!(relative(&parser, "2 wks ago"), Some(TimeDelta::weeks(2)));
+        assert_eq!(relative(&parser, "2 months ago"), Some(TimeDelta::days(60)));
+        assert_eq!(relative(&parser, "1 year ago"), Some(TimeDelta::days(365)));
+        assert_eq!(relative(&parser, "Yesterday"), Some(TimeDelta::days(1)));
+        assert_eq!(relative(&parser, "today"), Some(TimeDelta::zero()));
+        assert_eq!(relative(&parser, "3 days"), None);
+    }
+
+    #[test]
+    fn absolute_dates() {
+        let parser = DateParser::default();
+
+        assert_eq!(absolute(&parser, "August 12, 2023"), Some(day(2023, 8, 12)));
+        assert_eq!(absolute(&parser, "Aug 12, 2023"), Some(day(2023, 8, 12)));
+        assert_eq!(absolute(&parser, "Sept. 3, 2023 -"), Some(day(2023, 9, 3)));
+        assert_eq!(absolute(&parser, "3 Mar 2022"), Some(day(2022, 3, 3)));
+        assert_eq!(absolute(&parser, "12/25/2023"), Some(day(2023, 12, 25)));
+        assert_eq!(absolute(&parser, "Feb 30, 2023"), None);
+        assert_eq!(absolute(&parser, "Search results"), None);
+    }
+
+    #[test]
+    fn dates_without_year() {
+        let parser = DateParser::default();
+
+        assert_eq!(absolute(&parser, "Mar 3"), Some(day(2024, 3, 3)));
+        assert_eq!(absolute(&parser, "Mar 11"), Some(day(2024, 3, 11)));
+        // Would be in the future this year
+        assert_eq!(absolute(&parser, "Dec 24"), Some(day(2023, 12, 24)));
+    }
+
+    #[test]
+    fn iso_dates() {
+        let parser = DateParser::default();
+
+        assert_eq!(
+            absolute(&parser, "2023-05-03T00:00:00.0000000"),
+            Some(day(2023, 5, 3))
+        );
+        assert_eq!(
+            absolute(&parser, "2023-05-03T10:00:00+02:00"),
+            Some(day(2023, 5, 3) + TimeDelta::hours(8))
+        );
+        assert_eq!(absolute(&parser, "2023-05-03"), Some(day(2023, 5, 3)));
+    }
+
+    #[test]
+    fn engine_formats() {
+        let parser = DateParser::new(Some("%d %b %Y".to_string()), &ENGLISH);
+        assert_eq!(absolute(&parser, "03 Mar 2022"), Some(day(2022, 3, 3)));
+
+        let parser = DateParser::new(Some("%Y%m%d".to_string()), &ENGLISH);
+        assert_eq!(absolute(&parser, "20220303"), Some(day(2022, 3, 3)));
+    }
+
+    #[test]
+    fn german_dates() {
+        let parser = DateParser::new(None, &GERMAN);
+
+        assert_eq!(absolute(&parser, "3. März 2024"), Some(day(2024, 3, 3)));
+        assert_eq!(absolute(&parser, "12. Okt. 2023"), Some(day(2023, 10, 12)));
+        assert_eq!(absolute(&parser, "03.03.2024"), Some(day(2024, 3, 3)));
+        assert_eq!(
+            relative(&parser, "vor 2 Stunden"),
+            Some(TimeDelta::hours(2))
+        );
+        assert_eq!(relative(&parser, "vor einem Tag"), Some(TimeDelta::days(1)));
+        assert_eq!(relative(&parser, "gestern"), Some(TimeDelta::days(1)));
+    }
+}
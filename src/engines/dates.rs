@@ -0,0 +1,368 @@
+// Parsing of the dates engines show next to their results.
+//
+// Engines show dates in many shapes: relative ("3 days ago", "5h ago", "yesterday"),
+// absolute with or without a year ("March 3, 2024", "3 Mar"), or as ISO timestamps.
+// Absolute dates are parsed using the engine's own format first; otherwise the date is
+// split into words and numbers, whose month names and units depend on the locale.
+pub mod dates {
+    use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+
+    use crate::engines::engine_base::engine_base::SearchResultDate;
+
+    const DAY: i64 = 60 * 60 * 24;
+
+    /// Words that dates are written with in a language, all lowercase
+    #[derive(Debug)]
+    pub struct DateLocale {
+        pub code: &'static str,
+        /// Full name of every month; abbreviations are found by prefix
+        months: [&'static str; 12],
+        today: &'static [&'static str],
+        yesterday: &'static [&'static str],
+        /// Marks a relative date, e.g. "ago" in "3 days ago"
+        ago: &'static [&'static str],
+        /// Words used instead of the number 1, e.g. "an" in "an hour ago"
+        one: &'static [&'static str],
+        /// Seconds of each unit; a trailing "s" of a unit is ignored
+        units: &'static [(&'static str, i64)],
+        /// Formats of dates that are written using numbers only
+        numeric_formats: &'static [&'static str],
+    }
+
+    pub static ENGLISH: DateLocale = DateLocale {
+        code: "en",
+        months: [
+            "january",
+            "february",
+            "march",
+            "april",
+            "may",
+            "june",
+            "july",
+            "august",
+            "september",
+            "october",
+            "november",
+            "december",
+        ],
+        today: &["today", "now"],
+        yesterday: &["yesterday"],
+        ago: &["ago"],
+        one: &["a", "an", "one"],
+        units: &[
+            ("s", 1),
+            ("sec", 1),
+            ("second", 1),
+            ("m", 60),
+            ("min", 60),
+            ("minute", 60),
+            ("h", 60 * 60),
+            ("hr", 60 * 60),
+            ("hour", 60 * 60),
+            ("d", DAY),
+            ("day", DAY),
+            ("w", 7 * DAY),
+            ("wk", 7 * DAY),
+            ("week", 7 * DAY),
+            ("mo", 30 * DAY),
+            ("month", 30 * DAY),
+            ("y", 365 * DAY),
+            ("yr", 365 * DAY),
+            ("year", 365 * DAY),
+        ],
+        numeric_formats: &["%m/%d/%Y", "%m/%d/%y"],
+    };
+
+    pub static GERMAN: DateLocale = DateLocale {
+        code: "de",
+        months: [
+            "januar",
+            "februar",
+            "märz",
+            "april",
+            "mai",
+            "juni",
+            "juli",
+            "august",
+            "september",
+            "oktober",
+            "november",
+            "dezember",
+        ],
+        today: &["heute", "jetzt"],
+        yesterday: &["gestern"],
+        ago: &["vor"],
+        one: &["einem", "einer", "ein", "eine"],
+        units: &[
+            ("sek", 1),
+            ("sekunde", 1),
+            ("sekunden", 1),
+            ("min", 60),
+            ("minute", 60),
+            ("minuten", 60),
+            ("std", 60 * 60),
+            ("stunde", 60 * 60),
+            ("stunden", 60 * 60),
+            ("tag", DAY),
+            ("tage", DAY),
+            ("tagen", DAY),
+            ("woche", 7 * DAY),
+            ("wochen", 7 * DAY),
+            ("monat", 30 * DAY),
+            ("monate", 30 * DAY),
+            ("monaten", 30 * DAY),
+            ("jahr", 365 * DAY),
+            ("jahre", 365 * DAY),
+            ("jahren", 365 * DAY),
+        ],
+        numeric_formats: &["%d.%m.%Y", "%d.%m.%y"],
+    };
+
+    pub static LOCALES: [&DateLocale; 2] = [&ENGLISH, &GERMAN];
+
+    pub fn locale_by_code(code: &str) -> Option<&'static DateLocale> {
+        LOCALES
+            .iter()
+            .copied()
+            .find(|locale| locale.code.eq_ignore_ascii_case(code))
+    }
+
+    impl DateLocale {
+        /// Month of a full or abbreviated month name, e.g. "Sep." or "sept"
+        fn month(&self, word: &str) -> Option<u32> {
+            if word.chars().count() < 3 {
+                return None;
+            }
+
+            self.months
+                .iter()
+                .position(|month| month.starts_with(word))
+                .map(|index| index as u32 + 1)
+        }
+
+        fn unit_seconds(&self, word: &str) -> Option<i64> {
+            let find = |word: &str| {
+                self.units
+                    .iter()
+                    .find(|(unit, _)| *unit == word)
+                    .map(|(_, seconds)| *seconds)
+            };
+
+            find(word).or_else(|| find(word.strip_suffix('s')?))
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum DateToken {
+        Number { value: u32, digits: usize },
+        Word(String),
+    }
+
+    /// Split a date into lowercase words and numbers, e.g. "5h ago" into `5`, `h`, `ago`
+    fn tokenize(date: &str) -> Vec<DateToken> {
+        let mut tokens = Vec::new();
+        let mut chars = date.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_digit() {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    number.push(digit);
+                }
+
+                tokens.push(DateToken::Number {
+                    value: number.parse().unwrap_or(u32::MAX),
+                    digits: number.len(),
+                });
+            } else if c.is_alphabetic() {
+                let mut word = String::new();
+                while let Some(letter) = chars.next_if(|c| c.is_alphabetic()) {
+                    word.extend(letter.to_lowercase());
+                }
+
+                tokens.push(DateToken::Word(word));
+            } else {
+                chars.next();
+            }
+        }
+
+        tokens
+    }
+
+    /// Parses the dates of a single engine
+    #[derive(Clone, Debug)]
+    pub struct DateParser {
+        /// `chrono` format of the engine's absolute dates, tried before anything else
+        format: Option<String>,
+        locale: &'static DateLocale,
+    }
+
+    impl Default for DateParser {
+        fn default() -> Self {
+            Self::new(None, &ENGLISH)
+        }
+    }
+
+    impl DateParser {
+        pub fn new(format: Option<String>, locale: &'static DateLocale) -> Self {
+            Self { format, locale }
+        }
+
+        pub fn parse(&self, date: &str) -> Option<SearchResultDate> {
+            self.parse_at(date, Utc::now())
+        }
+
+        /// Parse `date` as if it was shown at `now`
+        pub fn parse_at(&self, date: &str, now: DateTime<Utc>) -> Option<SearchResultDate> {
+            let date = date
+                .trim()
+                .trim_matches(|c: char| c == '-' || c == '·' || c == ',' || c.is_whitespace());
+
+            if date.is_empty() {
+                return None;
+            }
+
+            let absolute = |date: DateTime<Utc>| SearchResultDate {
+                date,
+                is_relative: false,
+            };
+
+            if let Some(format) = &self.format {
+                if let Some(parsed) = parse_with_format(date, format) {
+                    return Some(absolute(parsed));
+                }
+            }
+
+            if let Some(parsed) = parse_iso(date) {
+                return Some(absolute(parsed));
+            }
+
+            let tokens = tokenize(date);
+
+            if let Some(parsed) = self.parse_relative(&tokens, now) {
+                return Some(SearchResultDate {
+                    date: parsed,
+                    is_relative: true,
+                });
+            }
+
+            self.locale
+                .numeric_formats
+                .iter()
+                .find_map(|format| parse_with_format(date, format))
+                .or_else(|| self.parse_month_name(&tokens, now))
+                .map(absolute)
+        }
+
+        /// "3 days ago", "5h ago", "vor 2 Stunden", "an hour ago", "yesterday"
+        fn parse_relative(
+            &self,
+            tokens: &[DateToken],
+            now: DateTime<Utc>,
+        ) -> Option<DateTime<Utc>> {
+            let is_word = |words: &[&str]| {
+                tokens.iter().any(
+                    |token| matches!(token, DateToken::Word(word) if words.contains(&word.as_str())),
+                )
+            };
+
+            if is_word(self.locale.yesterday) {
+                return now.checked_sub_signed(TimeDelta::try_seconds(DAY)?);
+            }
+
+            if !is_word(self.locale.ago) {
+                let has_number = tokens
+                    .iter()
+                    .any(|token| matches!(token, DateToken::Number { .. }));
+
+                return match is_word(self.locale.today) && !has_number {
+                    true => Some(now),
+                    false => None,
+                };
+            }
+
+            tokens.windows(2).find_map(|pair| {
+                let amount = match &pair[0] {
+                    DateToken::Number { value, .. } => *value as i64,
+                    DateToken::Word(word) if self.locale.one.contains(&word.as_str()) => 1,
+                    _ => return None,
+                };
+                let DateToken::Word(unit) = &pair[1] else {
+                    return None;
+                };
+                let seconds = amount.checked_mul(self.locale.unit_seconds(unit)?)?;
+
+                now.checked_sub_signed(TimeDelta::try_seconds(seconds)?)
+            })
+        }
+
+        /// "March 3, 2024", "3. März 2024", "Sep 12", "12 Sept."
+        fn parse_month_name(
+            &self,
+            tokens: &[DateToken],
+            now: DateTime<Utc>,
+        ) -> Option<DateTime<Utc>> {
+            let month = tokens.iter().find_map(|token| match token {
+                DateToken::Word(word) => self.locale.month(word),
+                _ => None,
+            })?;
+            let numbers = tokens.iter().filter_map(|token| match token {
+                DateToken::Number { value, digits } => Some((*value, *digits)),
+                _ => None,
+            });
+
+            let mut day = None;
+            let mut year = None;
+
+            for (value, digits) in numbers {
+                match digits {
+                    1 | 2 if day.is_none() => day = Some(value),
+                    4 if year.is_none() => year = Some(value as i32),
+                    _ => {}
+                }
+            }
+
+            let day = day?;
+            let date = match year {
+                Some(year) => NaiveDate::from_ymd_opt(year, month, day)?,
+                // Dates without a year are in the past year
+                None => {
+                    let date = NaiveDate::from_ymd_opt(now.year(), month, day)?;
+
+                    match date > now.date_naive() + TimeDelta::days(1) {
+                        true => NaiveDate::from_ymd_opt(now.year() - 1, month, day)?,
+                        false => date,
+                    }
+                }
+            };
+
+            Some(date.and_time(NaiveTime::MIN).and_utc())
+        }
+    }
+
+    /// Parse using a `chrono` format, which may contain a time and a timezone
+    fn parse_with_format(date: &str, format: &str) -> Option<DateTime<Utc>> {
+        if let Ok(parsed) = DateTime::parse_from_str(date, format) {
+            return Some(parsed.to_utc());
+        }
+
+        if let Ok(parsed) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(parsed.and_utc());
+        }
+
+        NaiveDate::parse_from_str(date, format)
+            .ok()
+            .map(|parsed| parsed.and_time(NaiveTime::MIN).and_utc())
+    }
+
+    /// "2024-03-03T12:00:00Z", "2024-03-03T12:00:00.0000000", "2024-03-03"
+    fn parse_iso(date: &str) -> Option<DateTime<Utc>> {
+        if let Ok(parsed) = DateTime::parse_from_rfc3339(date) {
+            return Some(parsed.to_utc());
+        }
+
+        ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d"]
+            .iter()
+            .find_map(|format| parse_with_format(date, format))
+    }
+}
@@ -0,0 +1,329 @@
+// Detection of parsers that broke because an engine changed its markup.
+//
+// For every engine, the share of complete responses without a single result is
+// tracked over its most recent responses. Once that rate spikes, the engine is
+// flagged as "parser likely broken" and the bodies of its empty responses are
+// saved to the diagnostics directory, so that the parser can be fixed using them.
+pub mod drift {
+    use std::{
+        collections::VecDeque,
+        error::Error,
+        fs,
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::SystemTime,
+    };
+
+    use ahash::AHashMap;
+    use chrono::{DateTime, Utc};
+    use reqwest::Url;
+    use serde::{Deserialize, Serialize};
+    use tokio::task;
+
+    use crate::engines::engine_base::engine_base::SearchEngine;
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(default)]
+    pub struct DriftConfig {
+        /// Number of most recent responses of an engine the zero-result rate is based on
+        pub window: usize,
+        /// Responses needed before an engine can be flagged
+        pub min_responses: usize,
+        /// Zero-result rate from which an engine is flagged
+        pub broken_rate: f64,
+        /// Directory the empty responses of flagged engines are saved to
+        pub directory: PathBuf,
+        /// Maximum number of saved responses; the oldest ones are deleted first
+        pub max_dumps: usize,
+    }
+
+    impl Default for DriftConfig {
+        fn default() -> Self {
+            Self {
+                window: 20,
+                min_responses: 5,
+                broken_rate: 0.5,
+                directory: PathBuf::from("./diagnostics"),
+                max_dumps: 50,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct DriftStatus {
+        pub responses: usize,
+        pub empty_responses: usize,
+        pub likely_broken: bool,
+    }
+
+    impl DriftStatus {
+        pub fn zero_result_rate(&self) -> f64 {
+            match self.responses {
+                0 => 0.0,
+                responses => self.empty_responses as f64 / responses as f64,
+            }
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct EngineDrift {
+        /// Whether each of the most recent responses was empty, oldest first
+        outcomes: VecDeque<bool>,
+        likely_broken: bool,
+    }
+
+    impl EngineDrift {
+        fn status(&self) -> DriftStatus {
+            DriftStatus {
+                responses: self.outcomes.len(),
+                empty_responses: self.outcomes.iter().filter(|is_empty| **is_empty).count(),
+                likely_broken: self.likely_broken,
+            }
+        }
+    }
+
+    /// Saved next to the body of an empty response
+    #[derive(Serialize)]
+    struct DumpMetadata {
+        engine: SearchEngine,
+        url: String,
+        captured_at: DateTime<Utc>,
+        body_size: usize,
+        responses: usize,
+        empty_responses: usize,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct ParserDriftMonitor {
+        config: DriftConfig,
+        states: Mutex<AHashMap<SearchEngine, EngineDrift>>,
+    }
+
+    impl ParserDriftMonitor {
+        pub fn new(config: DriftConfig) -> Self {
+            Self {
+                config,
+                states: Mutex::new(AHashMap::new()),
+            }
+        }
+
+        fn record(&self, engine: SearchEngine, is_empty: bool) -> DriftStatus {
+            let mut states = self.states.lock().unwrap();
+            let state = states.entry(engine).or_default();
+
+            state.outcomes.push_back(is_empty);
+            while state.outcomes.len() > self.config.window.max(1) {
+                state.outcomes.pop_front();
+            }
+
+            let status = state.status();
+            let likely_broken = status.responses >= self.config.min_responses
+                && status.zero_result_rate() >= self.config.broken_rate;
+
+            if likely_broken != state.likely_broken {
+                state.likely_broken = likely_broken;
+
+                if likely_broken {
+                    log::warn!(
+                        "Parser of {} likely broken: {} of its last {} responses had no results",
+                        engine,
+                        status.empty_responses,
+                        status.responses
+                    );
+                } else {
+                    log::info!("Parser of {} recovered", engine);
+                }
+            }
+
+            state.status()
+        }
+
+        /// A response of `engine` contained results
+        pub fn report_results(&self, engine: SearchEngine) {
+            self.record(engine, false);
+        }
+
+        /// A response of `engine` was received completely, but contained no results.
+        /// The body is saved on the blocking thread pool if the engine is flagged.
+        pub async fn report_empty_response(&self, engine: SearchEngine, url: &Url, body: Vec<u8>) {
+            let status = self.record(engine, true);
+
+            if !status.likely_broken || self.config.max_dumps == 0 {
+                return;
+            }
+
+            let directory = self.config.directory.clone();
+            let max_dumps = self.config.max_dumps;
+            let url = url.to_string();
+            let saved = task::spawn_blocking(move || {
+                dump(&directory, engine, url, &body, &status)?;
+                evict(&directory, max_dumps)
+            })
+            .await
+            .unwrap_or_else(|error| Err(error.into()));
+
+            if let Err(error) = saved {
+                log::error!("Could not save empty response of {}: {}", engine, error);
+            }
+        }
+
+        pub fn status(&self, engine: &SearchEngine) -> DriftStatus {
+            self.states
+                .lock()
+                .unwrap()
+                .get(engine)
+                .map(EngineDrift::status)
+                .unwrap_or_default()
+        }
+    }
+
+    fn dump(
+        directory: &Path,
+        engine: SearchEngine,
+        url: String,
+        body: &[u8],
+        status: &DriftStatus,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let captured_at = Utc::now();
+        let name = format!(
+            "{}-{}",
+            engine.html_class(),
+            captured_at.timestamp_nanos_opt().unwrap_or_default()
+        );
+        let metadata = DumpMetadata {
+            engine,
+            url,
+            captured_at,
+            body_size: body.len(),
+            responses: status.responses,
+            empty_responses: status.empty_responses,
+        };
+
+        fs::create_dir_all(directory)?;
+        fs::write(directory.join(format!("{}.html", name)), body)?;
+        fs::write(
+            directory.join(format!("{}.json", name)),
+            serde_json::to_string_pretty(&metadata)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Delete the oldest dumps until at most `max_dumps` are left
+    fn evict(directory: &Path, max_dumps: usize) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut dumps = fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .map(|entry| {
+                let modified = entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+
+                (modified, entry.path())
+            })
+            .collect::<Vec<_>>();
+
+        if dumps.len() <= max_dumps {
+            return Ok(());
+        }
+
+        dumps.sort();
+
+        for (_, path) in &dumps[..dumps.len() - max_dumps] {
+            // The body is useless without its metadata, so it's removed first
+            let _ = fs::remove_file(path.with_extension("html"));
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::engines::test_fixtures::test_fixtures::temporary_directory;
+
+        const EMPTY_BODY: &[u8] = b"<html><body>Changed markup</body></html>";
+
+        fn monitor(window: usize, min_responses: usize, broken_rate: f64) -> ParserDriftMonitor {
+            ParserDriftMonitor::new(DriftConfig {
+                window,
+                min_responses,
+                broken_rate,
+                max_dumps: 0,
+                ..DriftConfig::default()
+            })
+        }
+
+        /// Report a response of Brave for every outcome, `true` if it was empty
+        async fn report(drift: &ParserDriftMonitor, outcomes: &[bool]) -> DriftStatus {
+            let url = Url::parse("https://search.brave.com/search?q=test").unwrap();
+
+            for is_empty in outcomes {
+                match is_empty {
+                    true => {
+                        drift
+                            .report_empty_response(SearchEngine::Brave, &url, EMPTY_BODY.to_vec())
+                            .await
+                    }
+                    false => drift.report_results(SearchEngine::Brave),
+                }
+            }
+
+            drift.status(&SearchEngine::Brave)
+        }
+
+        #[tokio::test]
+        async fn rate_of_the_most_recent_responses() {
+            let drift = monitor(3, 1, 1.0);
+
+            assert_eq!(drift.status(&SearchEngine::Brave).zero_result_rate(), 0.0);
+
+            let status = report(&drift, &[true, true, false, false]).await;
+            assert_eq!((status.responses, status.empty_responses), (3, 1));
+            assert!((status.zero_result_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+            // Other engines are tracked separately
+            assert_eq!(drift.status(&SearchEngine::Bing), DriftStatus::default());
+        }
+
+        #[tokio::test]
+        async fn flagged_from_the_broken_rate() {
+            let drift = monitor(10, 4, 0.5);
+
+            // Not enough responses yet
+            assert!(!report(&drift, &[true, true, true]).await.likely_broken);
+            // 3 of 4
+            assert!(report(&drift, &[false]).await.likely_broken);
+            // 3 of 6 is still at the rate
+            assert!(report(&drift, &[false, false]).await.likely_broken);
+            // 3 of 7 is below it
+            assert!(!report(&drift, &[false]).await.likely_broken);
+        }
+
+        #[tokio::test]
+        async fn flags_engine_without_results() {
+            let directory = temporary_directory("drift");
+            let drift = ParserDriftMonitor::new(DriftConfig {
+                window: 4,
+                min_responses: 2,
+                broken_rate: 0.5,
+                directory: directory.clone(),
+                max_dumps: 2,
+            });
+
+            let status = report(&drift, &[true, false, true, true, true]).await;
+            assert!(status.likely_broken);
+            assert_eq!((status.responses, status.empty_responses), (4, 3));
+
+            // Three empty responses were received while flagged, only the last two are kept
+            let dumps = fs::read_dir(&directory).unwrap().count();
+            assert_eq!(dumps, 2 * 2);
+
+            assert!(!report(&drift, &[false, false, false]).await.likely_broken);
+
+            let _ = fs::remove_dir_all(&directory);
+        }
+    }
+}
@@ -1,56 +1,97 @@
 // Search engine parser for DuckDuckGo Search
 pub mod duckduckgo {
+    use std::sync::Arc;
+
     use lazy_static::lazy_static;
     use regex::Regex;
-    use tokio::sync::mpsc::Sender;
+    use reqwest::{Client, RequestBuilder};
 
-    use crate::{
-        engines::engine_base::engine_base::{
-            EngineBase, EnginePositions, SearchEngine, SearchResult,
-        },
-        helpers::helpers::build_default_client,
+    use crate::engines::{
+        dates::dates::DateParser,
+        engine_base::engine_base::{EngineBase, SearchEngine, SearchResult},
+        redirects::redirects::{Redirect, RedirectKind, UrlResolver},
+        registry::registry::Engine,
+        selectors::selectors::{ResultSelectors, SelectorMap, SelectorResultParser},
     };
 
     lazy_static! {
-        static ref RESULTS_START: Regex = Regex::new(r#"id=\"links\""#).unwrap();
-        static ref SINGLE_RESULT: Regex = Regex::new(r#"<div class="result results_links.*?<a.*?href="(?P<url>.*?)".*?>(?P<title>.*?)</a>.*?class="result__snippet".*?>(?P<description>.*?)</a>.*?class="clear".*?</div>(?P<end> </div>){2}"#).unwrap();
+        static ref SELECTORS: Arc<ResultSelectors> = Arc::new(
+            ResultSelectors::from_map(
+                &SelectorMap {
+                    result: "#links div.web-result".to_string(),
+                    title: "a.result__a".to_string(),
+                    url: "a.result__a@href".to_string(),
+                    description: ".result__snippet".to_string(),
+                    image: Some("img.result__icon__img@src".to_string()),
+                    // Dated results show an ISO timestamp after their URL
+                    date: Some(".result__extras__url > span:nth-of-type(2)".to_string()),
+                    // The HTML version shows neither sitelinks nor rich snippets
+                    breadcrumbs: None,
+                    sitelinks: None,
+                    attributes: None,
+                },
+                DateParser::default(),
+                UrlResolver::new(Some(URL), &REDIRECTS),
+            )
+            .unwrap()
+        );
+        static ref BLOCK_PAGE: Arc<Regex> =
+            Arc::new(Regex::new(r#"anomaly-modal|/anomaly\.js"#).unwrap());
     }
 
     const URL: &str = "https://html.duckduckgo.com/html";
 
+    // Results link to `//duckduckgo.com/l/?uddg=<url>`, which redirects to the result
+    static REDIRECTS: [Redirect; 1] = [Redirect {
+        host: "duckduckgo.com",
+        path: "/l/",
+        kind: RedirectKind::QueryParameter("uddg"),
+    }];
+
     #[derive(Clone, Debug)]
     pub struct DuckDuckGo {
-        positions: EnginePositions,
+        parser: SelectorResultParser,
     }
 
     impl EngineBase for DuckDuckGo {
-        fn parse_next<'a>(&mut self) -> Option<SearchResult> {
-            self.positions.handle_block_using_default_method(
-                &SINGLE_RESULT,
-                SearchEngine::DuckDuckGo,
-                None,
-            )
+        fn parse_next(&mut self) -> Option<SearchResult> {
+            self.parser.parse_next()
+        }
+
+        fn push_packet(&mut self, packet: &[u8]) {
+            self.parser.push_packet(packet)
         }
 
-        fn push_packet<'a>(&mut self, packet: impl Iterator<Item = &'a u8>) {
-            self.positions
-                .handle_start_check_using_default_method(&RESULTS_START, packet)
+        fn block_page_regex(&self) -> Option<&Regex> {
+            self.parser.block_page_regex()
         }
     }
 
     impl DuckDuckGo {
         pub fn new() -> Self {
             Self {
-                positions: EnginePositions::new(),
+                parser: SelectorResultParser::new(
+                    SearchEngine::DuckDuckGo,
+                    SELECTORS.clone(),
+                    Some(BLOCK_PAGE.clone()),
+                ),
             }
         }
+    }
+
+    impl Engine for DuckDuckGo {
+        fn kind(&self) -> SearchEngine {
+            SearchEngine::DuckDuckGo
+        }
+
+        fn parser(&self) -> Box<dyn EngineBase> {
+            Box::new(DuckDuckGo::new())
+        }
 
-        pub async fn search(&mut self, query: &str, tx: Sender<SearchResult>) -> Result<(), ()> {
-            let client = build_default_client();
+        fn build_request(&self, client: &Client, query: &str) -> RequestBuilder {
             let params = [("q", query)];
-            let request = client.post(URL).form(&params).send();
 
-            self.handle_request(request, tx).await
+            client.post(URL).form(&params)
         }
     }
 }
@@ -1,38 +1,63 @@
 pub mod engine_base {
     use core::fmt;
     use std::{
+        error::Error,
         fmt::{Debug, Display},
         hash::Hash,
         ops::Sub,
         sync::Arc,
+        time::Duration,
     };
 
-    use chrono::{DateTime, TimeDelta, TimeZone, Utc};
-    use futures::{lock::Mutex, Future, StreamExt};
+    use ahash::AHashSet;
+    use async_trait::async_trait;
+    use chrono::{DateTime, TimeZone, Utc};
+    use futures::{lock::Mutex, StreamExt};
     use lazy_static::lazy_static;
-    use phf::phf_map;
     use regex::Regex;
-    use reqwest::{Error, Response};
+    use reqwest::{RequestBuilder, StatusCode};
     use rustc_hash::FxHashMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use tokio::sync::mpsc::Sender;
-    use urlencoding::decode;
 
-    use crate::utils::utils::{decode_html_text, hash_string};
+    use crate::engines::{
+        dates::dates::DateParser, drift::drift::ParserDriftMonitor, extras::extras::build_extras,
+        redirects::redirects::UrlResolver,
+    };
+    use crate::recording::recording::ResponseRecording;
+    use crate::utils::utils::{
+        canonical_url, decode_html_attribute, decode_html_entities, decode_utf8_packet, hash_string,
+    };
 
     lazy_static! {
-        static ref STRIP: Regex = Regex::new(r"[\s\n]+").unwrap();
+        static ref CUSTOM_ENGINE_NAMES: std::sync::Mutex<AHashSet<&'static str>> =
+            std::sync::Mutex::new(AHashSet::new());
         static ref STRIP_HTML_TAGS: Regex =
             Regex::new(r#"<(?:"[^"]*"['"]*|'[^']*'['"]*|[^'">])+>"#).unwrap();
-        static ref RELATIVE_DATETIME_PARSER: Regex =
-            Regex::new(r#"(?P<amount>\d+) (?P<unit>second|minute|hour|day|week|month|year)s? ago"#)
-                .unwrap();
+        static ref LINK: Regex =
+            Regex::new(r#"<a\s[^>]*?href="(?P<url>[^"]*)"[^>]*>(?P<title>.*?)</a>"#).unwrap();
     }
 
+    // Number of bytes of the previous packet that are searched again for block page signatures
+    const BLOCK_PAGE_CHECK_OVERLAP: usize = 256;
+    // Number of bytes before the results start that are searched again for its marker
+    const RESULTS_START_CHECK_OVERLAP: usize = 256;
+    // Matched text is only removed from the block once there is this much of it
+    const CONSUMED_BLOCK_COMPACT_SIZE: usize = 16 * 1024;
+    // Longest text a single result may span, after collapsing whitespace. Text further
+    // from the end of the block can't be the start of a result once nothing matched.
+    const MAX_RESULT_LENGTH: usize = 32 * 1024;
+    // Bodies of responses are kept up to this size until their first result, so that
+    // empty responses can be saved for diagnostics
+    const MAX_DIAGNOSTICS_BODY_SIZE: usize = 4 * 1024 * 1024;
+
     #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
     pub enum SearchEngine {
         Brave,
         Bing,
         DuckDuckGo,
+        // Engines defined in the config file
+        Custom(&'static str),
     }
 
     impl Display for SearchEngine {
@@ -41,11 +66,108 @@ pub mod engine_base {
                 SearchEngine::Brave => write!(f, "Brave"),
                 SearchEngine::DuckDuckGo => write!(f, "DuckDuckGo"),
                 SearchEngine::Bing => write!(f, "Bing"),
+                SearchEngine::Custom(name) => write!(f, "{}", name),
+            }
+        }
+    }
+
+    impl SearchEngine {
+        /// Look up an engine by its name; unknown names become a `Custom` engine
+        pub fn from_name(name: &str) -> Self {
+            match name.to_lowercase().as_str() {
+                "brave" => SearchEngine::Brave,
+                "bing" => SearchEngine::Bing,
+                "duckduckgo" => SearchEngine::DuckDuckGo,
+                _ => {
+                    // Each name is only leaked once, so the amount of leaked memory is
+                    // bounded by the number of configured engines
+                    let mut names = CUSTOM_ENGINE_NAMES.lock().unwrap();
+
+                    match names.get(name) {
+                        Some(name) => SearchEngine::Custom(name),
+                        None => {
+                            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+                            names.insert(name);
+
+                            SearchEngine::Custom(name)
+                        }
+                    }
+                }
+            }
+        }
+
+        /// Name that is safe to use as an HTML class and inside IDs
+        pub fn html_class(&self) -> String {
+            self.to_string()
+                .to_lowercase()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect()
+        }
+    }
+
+    impl Serialize for SearchEngine {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&self.to_string())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SearchEngine {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Ok(SearchEngine::from_name(&String::deserialize(deserializer)?))
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum EngineError {
+        /// The engine could not be reached (DNS failure, connection reset, ...)
+        Network(String),
+        /// The engine answered with a non-success status code
+        HttpStatus(u16),
+        Timeout,
+        /// The engine served a captcha or otherwise refused to answer
+        Blocked,
+        /// The engine was skipped because it is over its outbound rate limit
+        RateLimited,
+        /// The response was received completely, but no result could be parsed
+        NoResults,
+        /// The receiver of the results is gone, e.g. because the user closed the page
+        Cancelled,
+    }
+
+    impl Display for EngineError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                EngineError::Network(reason) => write!(f, "network error ({})", reason),
+                EngineError::HttpStatus(status) => write!(f, "HTTP {}", status),
+                EngineError::Timeout => write!(f, "timed out"),
+                EngineError::Blocked => write!(f, "blocked"),
+                EngineError::RateLimited => write!(f, "rate limited"),
+                EngineError::NoResults => write!(f, "no results"),
+                EngineError::Cancelled => write!(f, "cancelled"),
+            }
+        }
+    }
+
+    impl std::error::Error for EngineError {}
+
+    impl From<reqwest::Error> for EngineError {
+        fn from(error: reqwest::Error) -> Self {
+            if error.is_timeout() {
+                return EngineError::Timeout;
+            }
+
+            match error.status() {
+                Some(status) => EngineError::HttpStatus(status.as_u16()),
+                None => EngineError::Network(match error.source() {
+                    Some(source) => source.to_string(),
+                    None => error.to_string(),
+                }),
             }
         }
     }
 
-    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
     pub struct SearchResultDate {
         pub date: DateTime<Utc>,
         // true if original date wasn't available and only
@@ -53,7 +175,38 @@ pub mod engine_base {
         pub is_relative: bool,
     }
 
-    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    /// Link to a subpage of a result, shown below it
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    pub struct Sitelink {
+        pub title: String,
+        pub url: String,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    pub struct Rating {
+        /// Rating in tenths, e.g. 45 for 4.5
+        pub value: u32,
+        /// Best possible rating, e.g. 5 for stars
+        pub scale: u32,
+        /// Number of ratings the value is based on
+        pub count: Option<u32>,
+    }
+
+    /// Details that engines only show for some results
+    #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct ResultExtras {
+        pub sitelinks: Vec<Sitelink>,
+        /// Path of the page on its site, e.g. `["wiki", "Test"]`
+        pub breadcrumbs: Vec<String>,
+        pub rating: Option<Rating>,
+        /// Length of a video or audio in seconds
+        pub duration: Option<u32>,
+        /// Number of replies or answers of a forum thread
+        pub replies: Option<u32>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
     pub struct SearchResult {
         pub title: String,
         pub url: String,
@@ -61,160 +214,324 @@ pub mod engine_base {
         pub engine: SearchEngine,
         pub image_url: Option<String>,
         pub date: Option<SearchResultDate>,
+        // Results cached before extras existed don't have them
+        #[serde(default)]
+        pub extras: ResultExtras,
+        /// Position among the results of its engine, starting at 1; 0 if unknown
+        #[serde(default)]
+        pub position: u32,
     }
 
     impl Hash for SearchResult {
         fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-            self.url.hash(state);
+            self.canonical_url().hash(state);
         }
     }
 
     impl SearchResult {
+        /// Url that is the same for all engines that found this result
+        pub fn canonical_url(&self) -> String {
+            canonical_url(&self.url)
+        }
+
         pub fn get_html_id(&self) -> String {
             // IDs must start with a letter, so we add an "h" (html ID) to the beginning
-            format!("h{:X}", hash_string(&self.url),)
+            format!("h{:X}", hash_string(&self.canonical_url()),)
         }
     }
 
-    pub trait EngineBase {
-        fn parse_next<'a>(&mut self) -> Option<SearchResult>;
+    /// Everything that happens during a search, in the order it happens
+    #[derive(Clone, Debug)]
+    pub enum SearchEvent {
+        Started {
+            engine: SearchEngine,
+        },
+        /// The engine started sending its response body
+        FirstByte {
+            engine: SearchEngine,
+        },
+        Result(SearchResult),
+        Finished {
+            engine: SearchEngine,
+            duration: Duration,
+            count: usize,
+        },
+        Failed {
+            engine: SearchEngine,
+            error: EngineError,
+        },
+    }
+
+    /// Packet-level parser of a single search engine response.
+    ///
+    /// A new parser is created for every request, so implementations may keep
+    /// any state they need between packets.
+    #[async_trait]
+    pub trait EngineBase: Send {
+        fn parse_next(&mut self) -> Option<SearchResult>;
+
+        fn push_packet(&mut self, packet: &[u8]);
 
-        fn push_packet<'a>(&mut self, packet: impl Iterator<Item = &'a u8>);
+        /// Pattern that only matches captcha or block pages of the engine
+        fn block_page_regex(&self) -> Option<&Regex> {
+            None
+        }
 
         /// Push packet to internal block and return next available search result, if available
-        fn parse_packet<'a>(
-            &mut self,
-            packet: impl Iterator<Item = &'a u8>,
-        ) -> Option<SearchResult> {
+        fn parse_packet(&mut self, packet: &[u8]) -> Option<SearchResult> {
             self.push_packet(packet);
 
             self.parse_next()
         }
 
+        /// Stream the response of `request` through the parser and send every result
+        /// to `tx` as soon as it is available. Returns the number of results sent.
+        ///
+        /// The raw response is saved to `recording`, if given.
         async fn handle_request(
             &mut self,
-            request: impl Future<Output = Result<Response, Error>>,
-            tx: Sender<SearchResult>,
-        ) -> Result<(), ()> {
-            let req = request.await.unwrap();
+            engine: SearchEngine,
+            request: RequestBuilder,
+            mut recording: Option<ResponseRecording>,
+            drift: Option<&ParserDriftMonitor>,
+            tx: Sender<SearchEvent>,
+        ) -> Result<usize, EngineError> {
+            let result = self
+                .stream_response(engine, request, recording.as_mut(), drift, tx)
+                .await;
+
+            if let Some(recording) = recording {
+                recording.save().await;
+            }
+
+            result
+        }
+
+        async fn stream_response(
+            &mut self,
+            engine: SearchEngine,
+            request: RequestBuilder,
+            mut recording: Option<&mut ResponseRecording>,
+            drift: Option<&ParserDriftMonitor>,
+            tx: Sender<SearchEvent>,
+        ) -> Result<usize, EngineError> {
+            let req = request.send().await?;
             let url = req.url().clone();
+
+            if let Some(recording) = &mut recording {
+                recording.record_headers(&url, req.status());
+            }
+
+            if req.status() == StatusCode::TOO_MANY_REQUESTS {
+                return Err(EngineError::Blocked);
+            }
+
+            if !req.status().is_success() {
+                return Err(EngineError::HttpStatus(req.status().as_u16()));
+            }
+
             let mut stream = req.bytes_stream();
 
-            let mut debug_has_fetched_once = false;
-            let mut debug_content = Vec::new();
+            let mut count: usize = 0;
+            let mut has_received_data = false;
+            let mut block_check_window: Vec<u8> = Vec::new();
+            let mut body = Vec::new();
             if cfg!(debug_assertions) {
                 println!("Requesting: {}", url);
             }
 
             while let Some(chunk) = stream.next().await {
-                let buffer = chunk.unwrap();
+                let buffer = chunk?;
 
-                self.push_packet(buffer.iter());
+                if let Some(recording) = &mut recording {
+                    recording.record_chunk(&buffer);
+                }
 
-                if cfg!(debug_assertions) {
-                    debug_content.extend(buffer);
+                if !has_received_data {
+                    has_received_data = true;
+
+                    // Only an indicator for the UI, so it's fine if the receiver is gone
+                    let _ = tx.send(SearchEvent::FirstByte { engine }).await;
                 }
 
-                while let Some(result) = self.parse_next() {
-                    if cfg!(debug_assertions) {
-                        debug_has_fetched_once = true;
+                // Block pages never contain results, so there's no need to check any further
+                if count == 0 {
+                    if let Some(block_page_regex) = self.block_page_regex() {
+                        block_check_window.extend_from_slice(&buffer);
+
+                        if block_page_regex.is_match(&String::from_utf8_lossy(&block_check_window))
+                        {
+                            return Err(EngineError::Blocked);
+                        }
+
+                        // Keep the end so that signatures split across packets are found too
+                        let consumed = block_check_window
+                            .len()
+                            .saturating_sub(BLOCK_PAGE_CHECK_OVERLAP);
+                        block_check_window.drain(..consumed);
                     }
+                }
+
+                self.push_packet(&buffer);
+
+                if count == 0 && drift.is_some() {
+                    let length = buffer.len().min(MAX_DIAGNOSTICS_BODY_SIZE - body.len());
+                    body.extend_from_slice(&buffer[..length]);
+                }
 
-                    if tx.send(result).await.is_err() {
-                        return Err(());
+                while let Some(mut result) = self.parse_next() {
+                    count += 1;
+                    result.position = count as u32;
+
+                    if tx.send(SearchEvent::Result(result)).await.is_err() {
+                        return Err(EngineError::Cancelled);
                     }
                 }
             }
 
-            while let Some(result) = self.parse_next() {
+            while let Some(mut result) = self.parse_next() {
+                count += 1;
+                result.position = count as u32;
+
+                if tx.send(SearchEvent::Result(result)).await.is_err() {
+                    return Err(EngineError::Cancelled);
+                }
+            }
+
+            if count == 0 {
                 if cfg!(debug_assertions) {
-                    debug_has_fetched_once = true;
+                    println!("No results for: {}", url);
                 }
 
-                if tx.send(result).await.is_err() {
-                    return Err(());
+                if let Some(drift) = drift {
+                    drift.report_empty_response(engine, &url, body).await;
                 }
+
+                return Err(EngineError::NoResults);
+            }
+
+            if let Some(drift) = drift {
+                drift.report_results(engine);
             }
 
             if cfg!(debug_assertions) {
-                if debug_has_fetched_once {
-                    println!("Finished fetching: {}", url);
-                } else {
-                    println!("{}", "==============");
-                    println!("No results for: {}", url);
-                    println!("{}", String::from_utf8_lossy(&debug_content));
-                }
+                println!("Finished fetching: {}", url);
             }
 
-            Ok(())
+            Ok(count)
         }
     }
 
+    /// Text of an HTML fragment with its tags removed and its entities decoded
+    fn html_to_text(html: &str) -> String {
+        // Tags are stripped first, so that escaped tags are kept as text
+        decode_html_entities(&STRIP_HTML_TAGS.replace_all(html, ""))
+    }
+
+    /// State of a regex based parser.
+    ///
+    /// Packets are appended to `previous_block` with all whitespace collapsed into single
+    /// spaces. Results are matched starting at `cursor`, so text that has already been
+    /// matched is never searched again. Results longer than `MAX_RESULT_LENGTH` may be
+    /// missed if they arrive in several packets.
     #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
     pub struct EnginePositions {
+        /// Text after the results start, or the end of the text before it
+        /// while the start hasn't been found yet
         pub previous_block: String,
         pub started: bool,
+        // Bytes of a UTF-8 character that is split across packets
+        pub pending_bytes: Vec<u8>,
+        /// Position in `previous_block` up to which results have been matched
+        pub cursor: usize,
+        // Whether `previous_block` ends with collapsed whitespace, so that
+        // whitespace split across packets is collapsed as well
+        ends_with_whitespace: bool,
     }
 
-    static UNIT_VALUES_MAP: phf::Map<&'static str, u32> = phf_map! {
-        "second" => 1,
-        "minute" => 60,
-        "hour" => 60 * 60,
-        "day" => 60 * 60 * 24,
-        "week" => 60 * 60 * 24 * 7,
-        "month" => 60 * 60 * 30,
-        "year" => 60 * 60 * 365,
-    };
-
     impl EnginePositions {
         pub fn new() -> Self {
             EnginePositions {
                 previous_block: String::new(),
                 started: false,
+                pending_bytes: Vec::new(),
+                cursor: 0,
+                ends_with_whitespace: false,
             }
         }
 
-        pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
-            let raw_date_stripped = date.split_whitespace().collect::<Vec<&str>>().join(" ");
-
-            if let Some(capture) = RELATIVE_DATETIME_PARSER.captures(&raw_date_stripped) {
-                let now = Utc::now();
-                let amount = capture.name("amount")?.as_str().parse::<i64>().ok()?;
-                let unit = capture.name("unit")?.as_str();
+        /// Mark everything before `position` as matched.
+        /// Positions that aren't on a character boundary are moved to the next one.
+        pub fn slice_remaining_block(&mut self, position: &usize) {
+            let mut position = (*position).min(self.previous_block.len());
 
-                let multiplier = UNIT_VALUES_MAP.get(&unit)?.clone() as i64;
-                let seconds_elapsed = amount * multiplier;
+            while !self.previous_block.is_char_boundary(position) {
+                position += 1;
+            }
 
-                let publish_date = now - TimeDelta::seconds(seconds_elapsed);
+            self.cursor = self.cursor.max(position);
+        }
 
-                Some(publish_date)
-            } else {
-                None
+        /// Remove matched text from the block once there is enough of it.
+        /// Waiting for a large enough amount keeps the copying of the rest linear overall.
+        fn compact_block(&mut self) {
+            if self.cursor >= CONSUMED_BLOCK_COMPACT_SIZE
+                && self.cursor * 2 >= self.previous_block.len()
+            {
+                self.previous_block.drain(..self.cursor);
+                self.cursor = 0;
             }
         }
 
-        pub fn slice_remaining_block(&mut self, start_position: &usize) {
-            let previous_block_bytes = self.previous_block.as_bytes().to_vec();
-            let remaining_bytes = previous_block_bytes[*start_position..].to_vec();
-            let remaining_text = String::from_utf8(remaining_bytes).unwrap();
+        /// Append `text` to the block, collapsing all whitespace into single spaces
+        fn push_collapsed(&mut self, text: &str) {
+            for (index, part) in text.split(char::is_whitespace).enumerate() {
+                if index > 0 && !self.ends_with_whitespace {
+                    self.previous_block.push(' ');
+                    self.ends_with_whitespace = true;
+                }
 
-            self.previous_block.clear();
-            self.previous_block.push_str(&remaining_text);
+                if !part.is_empty() {
+                    self.previous_block.push_str(part);
+                    self.ends_with_whitespace = false;
+                }
+            }
         }
 
-        pub fn handle_start_check_using_default_method<'a>(
+        pub fn handle_start_check_using_default_method(
             &mut self,
             results_start_regex: &Regex,
-            packet: impl Iterator<Item = &'a u8>,
+            packet: &[u8],
         ) {
-            let bytes: Vec<u8> = packet.map(|bit| *bit).collect();
-            let raw_text = String::from_utf8_lossy(&bytes);
-            let text = STRIP.replace_all(&raw_text, " ");
+            self.compact_block();
+
+            let mut pending_bytes = std::mem::take(&mut self.pending_bytes);
+            decode_utf8_packet(&mut pending_bytes, packet, |text| self.push_collapsed(text));
+            self.pending_bytes = pending_bytes;
 
             if self.started {
-                self.previous_block.push_str(&text);
-            } else {
-                self.started = results_start_regex.is_match(&text);
+                return;
+            }
+
+            match results_start_regex.find(&self.previous_block) {
+                Some(start) => {
+                    // Results may start in the same packet as the marker
+                    self.previous_block.drain(..start.end());
+                    self.started = true;
+                }
+                None => {
+                    // Keep the end so that a marker split across packets is found too
+                    let mut keep_from = self
+                        .previous_block
+                        .len()
+                        .saturating_sub(RESULTS_START_CHECK_OVERLAP);
+
+                    while !self.previous_block.is_char_boundary(keep_from) {
+                        keep_from += 1;
+                    }
+
+                    self.previous_block.drain(..keep_from);
+                }
             }
         }
 
@@ -222,68 +539,102 @@ pub mod engine_base {
             &mut self,
             single_result_regex: &Regex,
             engine: SearchEngine,
-            date_format: Option<&str>,
+            date_parser: &DateParser,
+            url_resolver: &UrlResolver,
         ) -> Option<SearchResult> {
-            if self.started {
-                if let Some(capture) = single_result_regex.captures(&self.previous_block.to_owned())
-                {
-                    let title = decode(capture.name("title").unwrap().as_str())
-                        .unwrap()
-                        .into_owned();
-                    let description_raw =
-                        decode_html_text(capture.name("description").unwrap().as_str()).unwrap();
-                    let description = STRIP_HTML_TAGS
-                        .replace_all(&description_raw, "")
-                        .into_owned();
-                    let url = decode(capture.name("url").unwrap().as_str())
-                        .unwrap()
-                        .into_owned();
-                    let image = match capture.name("image") {
-                        Some(image) => Some(image.as_str().to_string()),
-                        None => None,
-                    };
-
-                    let mut publish_date: Option<SearchResultDate> = None;
-
-                    if date_format.is_some() {
-                        let date = capture.name("date");
-                        publish_date = match date {
-                            Some(date) => {
-                                match DateTime::parse_from_str(date.as_str(), date_format.unwrap())
-                                {
-                                    Ok(parsed_date) => Some(SearchResultDate {
-                                        date: parsed_date.to_utc(),
-                                        is_relative: false,
-                                    }),
-                                    Err(_) => match EnginePositions::parse_date(&date.as_str()) {
-                                        Some(parsed_date) => Some(SearchResultDate {
-                                            date: parsed_date.to_utc(),
-                                            is_relative: true,
-                                        }),
-                                        None => None,
-                                    },
-                                }
-                            }
-                            None => None,
-                        };
-                    }
+            if !self.started {
+                return None;
+            }
 
-                    let result = SearchResult {
-                        title,
-                        description,
-                        url,
-                        engine,
-                        image_url: image,
-                        date: publish_date,
-                    };
+            while let Some(capture) =
+                single_result_regex.captures(&self.previous_block[self.cursor..])
+            {
+                let match_length = capture.get(0).map_or(0, |whole| whole.end());
+                let end_position = self.cursor + match_length;
 
-                    let end_position = capture.get(0).unwrap().end();
+                // An empty match would return the same result forever
+                if match_length == 0 {
+                    return None;
+                }
+
+                let (Some(title), Some(description), Some(url)) = (
+                    capture.name("title"),
+                    capture.name("description"),
+                    capture.name("url"),
+                ) else {
+                    // Skip matches that are missing required fields, e.g. because of
+                    // optional groups in a configured regex
                     self.slice_remaining_block(&end_position);
 
-                    return Some(result);
-                }
+                    continue;
+                };
+
+                let Some(url) = url_resolver.resolve(&decode_html_attribute(url.as_str())) else {
+                    // Links that don't lead to a web page, e.g. `javascript:` links
+                    self.slice_remaining_block(&end_position);
+
+                    continue;
+                };
+                let title = html_to_text(title.as_str());
+                let description = html_to_text(description.as_str());
+                let image = capture
+                    .name("image")
+                    .map(|image| decode_html_attribute(image.as_str()));
+
+                let publish_date = capture
+                    .name("date")
+                    .and_then(|date| date_parser.parse(&html_to_text(date.as_str())));
+
+                // Links inside the `sitelinks` group are the sitelinks
+                let sitelinks = capture.name("sitelinks").map_or(Vec::new(), |sitelinks| {
+                    LINK.captures_iter(sitelinks.as_str())
+                        .filter_map(|link| {
+                            Some((
+                                html_to_text(&link["title"]),
+                                url_resolver.resolve(&decode_html_attribute(&link["url"]))?,
+                            ))
+                        })
+                        .collect()
+                });
+                let extras = build_extras(
+                    &url,
+                    capture
+                        .name("breadcrumbs")
+                        .map(|breadcrumbs| html_to_text(breadcrumbs.as_str()))
+                        .as_deref(),
+                    sitelinks,
+                    // Tags are replaced by spaces, so that values of adjacent elements stay apart
+                    capture
+                        .name("attributes")
+                        .map(|attributes| {
+                            decode_html_entities(
+                                &STRIP_HTML_TAGS.replace_all(attributes.as_str(), " "),
+                            )
+                        })
+                        .as_deref(),
+                );
+
+                let result = SearchResult {
+                    title,
+                    description,
+                    url,
+                    engine,
+                    image_url: image,
+                    date: publish_date,
+                    extras,
+                    position: 0,
+                };
+
+                self.slice_remaining_block(&end_position);
+
+                return Some(result);
             }
 
+            // Without this, text that never matches, e.g. everything after the last result,
+            // would be searched again for every packet, which is quadratic
+            let tail_start = self.previous_block.len().saturating_sub(MAX_RESULT_LENGTH);
+            self.slice_remaining_block(&tail_start);
+
             None
         }
     }
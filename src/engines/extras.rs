@@ -0,0 +1,187 @@
+// Extraction of the details engines show for some results: sitelinks, breadcrumb
+// paths, ratings, video durations and reply counts of forum threads.
+//
+// Engines extract the raw text of these (and the links of sitelinks); the text is
+// then searched for the values, e.g. "Rating: 4.5/5 (1,234)" or "12 replies".
+pub mod extras {
+    use ahash::AHashSet;
+    use lazy_static::lazy_static;
+    use regex::Regex;
+    use reqwest::Url;
+
+    use crate::engines::engine_base::engine_base::{Rating, ResultExtras, Sitelink};
+
+    const MAX_SITELINKS: usize = 8;
+    const BREADCRUMB_SEPARATORS: [char; 2] = ['›', '»'];
+
+    lazy_static! {
+        // "4.5/5", "4,5 out of 5"; the scale must not be followed by more of a date
+        static ref RATING: Regex = Regex::new(
+            r"(?i)(?P<value>\d{1,3}(?:[.,]\d{1,2})?)\s*(?:/|out of|von)\s*(?P<scale>\d{1,3})(?:$|[^/.\d])"
+        )
+        .unwrap();
+        // "Rating: 4.5", "★ 4.5" without a scale, which is assumed to be 5
+        static ref STARS: Regex =
+            Regex::new(r"(?i)(?:rating|rated|bewertung|★)\s*:?\s*(?P<value>\d(?:[.,]\d{1,2})?)").unwrap();
+        static ref RATING_COUNT: Regex = Regex::new(
+            r"(?i)^\D{0,3}\((?P<count>\d[\d,.]*\s*[km]?)\)|(?P<counted>\d[\d,.]*\s*[km]?)\s+(?:reviews?|ratings?|votes?|bewertungen)"
+        )
+        .unwrap();
+        // "3:45", "1:02:03", but not "10:30 am"
+        static ref DURATION: Regex = Regex::new(
+            r"(?i)(?:^|[^\d:])(?P<duration>(?:\d{1,2}:)?\d{1,2}:\d{2})(?P<time_of_day>\s*[ap]\.?m\b)?(?:$|[^\d:])"
+        )
+        .unwrap();
+        static ref REPLIES: Regex = Regex::new(
+            r"(?i)(?P<count>\d[\d,.]*\s*[km]?)\s*(?:replies|reply|answers?|comments?|posts?|antworten)\b|(?:replies|answers|comments|posts)\s*:\s*(?P<labelled>\d[\d,.]*\s*[km]?)"
+        )
+        .unwrap();
+    }
+
+    /// Build the extras of the result at `url` from the raw values its engine found
+    pub fn build_extras(
+        url: &str,
+        breadcrumbs: Option<&str>,
+        sitelinks: Vec<(String, String)>,
+        attributes: Option<&str>,
+    ) -> ResultExtras {
+        let attributes = attributes.unwrap_or_default();
+
+        ResultExtras {
+            sitelinks: collect_sitelinks(url, sitelinks),
+            breadcrumbs: breadcrumbs
+                .map(|breadcrumbs| parse_breadcrumbs(breadcrumbs, url))
+                .unwrap_or_default(),
+            rating: parse_rating(attributes),
+            duration: parse_duration(attributes),
+            replies: parse_replies(attributes),
+        }
+    }
+
+    /// Resolve the `(title, url)` pairs of sitelinks against the result's url,
+    /// skipping links to the result itself and duplicates
+    pub fn collect_sitelinks(url: &str, links: Vec<(String, String)>) -> Vec<Sitelink> {
+        let base = Url::parse(url).ok();
+        let mut seen = AHashSet::new();
+        seen.insert(url.to_string());
+
+        links
+            .into_iter()
+            .filter_map(|(title, link)| {
+                let link = match &base {
+                    Some(base) => base.join(&link).ok()?.to_string(),
+                    None => link,
+                };
+
+                match !title.is_empty() && seen.insert(link.clone()) {
+                    true => Some(Sitelink { title, url: link }),
+                    false => None,
+                }
+            })
+            .take(MAX_SITELINKS)
+            .collect()
+    }
+
+    /// "› wiki › Test" or "https://en.wikipedia.org › wiki › Test" into `["wiki", "Test"]`
+    pub fn parse_breadcrumbs(text: &str, url: &str) -> Vec<String> {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        // The site is either shown as the first crumb or separately
+        let is_site = |crumb: &str| {
+            crumb.contains('/')
+                || host
+                    .as_deref()
+                    .is_some_and(|host| host == crumb || host.strip_prefix("www.") == Some(crumb))
+        };
+
+        text.split(BREADCRUMB_SEPARATORS)
+            .map(|crumb| crumb.split_whitespace().collect::<Vec<_>>().join(" "))
+            .enumerate()
+            .filter(|(index, crumb)| !(crumb.is_empty() || *index == 0 && is_site(crumb)))
+            .map(|(_, crumb)| crumb)
+            .collect()
+    }
+
+    /// "4.5/5 (1,234)", "Rated 4.5 out of 5 · 120 reviews", "★ 4.2"
+    pub fn parse_rating(text: &str) -> Option<Rating> {
+        let (value, scale, end) = match RATING.captures(text) {
+            Some(capture) => (
+                parse_decimal(capture.name("value")?.as_str())?,
+                capture.name("scale")?.as_str().parse::<u32>().ok()?,
+                capture.name("scale")?.end(),
+            ),
+            None => {
+                let capture = STARS.captures(text)?;
+                let value = capture.name("value")?;
+
+                (parse_decimal(value.as_str())?, 5, value.end())
+            }
+        };
+
+        if !matches!(scale, 5 | 10 | 100) || value > scale as f64 {
+            return None;
+        }
+
+        let count = RATING_COUNT.captures(&text[end..]).and_then(|capture| {
+            parse_count(capture.name("count").or(capture.name("counted"))?.as_str())
+        });
+
+        Some(Rating {
+            value: (value * 10.0).round() as u32,
+            scale,
+            count,
+        })
+    }
+
+    /// "3:45" or "1:02:03" in seconds
+    pub fn parse_duration(text: &str) -> Option<u32> {
+        let duration = DURATION
+            .captures_iter(text)
+            .find(|capture| capture.name("time_of_day").is_none())?
+            .name("duration")?;
+
+        duration
+            .as_str()
+            .split(':')
+            .try_fold(0u32, |seconds, part| {
+                seconds.checked_mul(60)?.checked_add(part.parse().ok()?)
+            })
+    }
+
+    /// "12 replies", "3 answers", "Posts: 40"
+    pub fn parse_replies(text: &str) -> Option<u32> {
+        let capture = REPLIES.captures(text)?;
+
+        parse_count(capture.name("count").or(capture.name("labelled"))?.as_str())
+    }
+
+    /// "1,234", "1.234", "1.2K" or "3M"
+    fn parse_count(count: &str) -> Option<u32> {
+        let count = count.trim();
+        let (number, factor) = match count.chars().last()?.to_ascii_lowercase() {
+            'k' => (&count[..count.len() - 1], 1_000.0),
+            'm' => (&count[..count.len() - 1], 1_000_000.0),
+            _ => {
+                let digits = count
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .collect::<String>();
+
+                return digits.parse().ok();
+            }
+        };
+
+        let count = parse_decimal(number.trim())? * factor;
+
+        match count <= u32::MAX as f64 {
+            true => Some(count as u32),
+            false => None,
+        }
+    }
+
+    /// "4.5" or "4,5"
+    fn parse_decimal(number: &str) -> Option<f64> {
+        number.replace(',', ".").parse().ok()
+    }
+}
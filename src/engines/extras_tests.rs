@@ -0,0 +1,198 @@
+// Tests of the extraction of sitelinks, breadcrumbs, ratings, durations and replies
+pub mod extras_tests {
+    use crate::{
+        engines::{
+            bing::bing::Bing,
+            brave::brave::Brave,
+            engine_base::engine_base::{EngineBase, Rating, SearchEngine, SearchResult, Sitelink},
+            extras::extras::{
+                collect_sitelinks, parse_breadcrumbs, parse_duration, parse_rating, parse_replies,
+            },
+            test_fixtures::test_fixtures::{search_result, BRAVE_EXAMPLE},
+        },
+        static_files::static_files::render_result,
+    };
+
+    const BING_RESPONSE: &str = r#"<ol id="b_results"><li class="b_algo"><div class="b_tpcn"><a class="tilk"><div class="siteicon"><img src="https://example.com/icon.png"></div><div class="b_attribution" u="1|2|3"><cite>https://www.example.com › forum › thread</cite></div></a></div><h2><a href="https://www.example.com/forum/thread">Thread &amp; answers</a></h2><div class="b_caption"><p class="b_lineclamp2"><span class="news_dt">Mar 3, 2024</span>&ensp;&#0183;&ensp;A description</p><div class="b_factrow">Rating: 4.5/5<span>(120)</span> · 12 replies</div></div><ul class="b_vList b_divsec"><li><a href="https://www.example.com/forum">Forum</a></li><li><a href="https://www.bing.com/ck/a?!&amp;&amp;p=1&amp;u=a1aHR0cHM6Ly93d3cuZXhhbXBsZS5jb20vZm9ydW0vbmV3&amp;ntb=1">New &amp; hot</a></li></ul></li></ol>"#;
+
+    fn parse(mut parser: impl EngineBase, response: &[u8]) -> Vec<SearchResult> {
+        parser.push_packet(response);
+
+        std::iter::from_fn(|| parser.parse_next()).collect()
+    }
+
+    #[test]
+    fn breadcrumbs() {
+        let url = "https://en.wikipedia.org/wiki/Test";
+
+        assert_eq!(parse_breadcrumbs("› wiki › Test", url), ["wiki", "Test"]);
+        assert_eq!(
+            parse_breadcrumbs("https://en.wikipedia.org › wiki ›  Test ", url),
+            ["wiki", "Test"]
+        );
+        assert_eq!(
+            parse_breadcrumbs("www.example.com » docs", "https://www.example.com/docs"),
+            ["docs"]
+        );
+        assert!(parse_breadcrumbs("https://en.wikipedia.org/wiki/Test", url).is_empty());
+    }
+
+    #[test]
+    fn ratings() {
+        assert_eq!(
+            parse_rating("Rating: 4.5/5 (1,234)"),
+            Some(Rating {
+                value: 45,
+                scale: 5,
+                count: Some(1234)
+            })
+        );
+        assert_eq!(
+            parse_rating("Rated 8 out of 10 · 1.2K votes"),
+            Some(Rating {
+                value: 80,
+                scale: 10,
+                count: Some(1200)
+            })
+        );
+        assert_eq!(
+            parse_rating("★ 4,2"),
+            Some(Rating {
+                value: 42,
+                scale: 5,
+                count: None
+            })
+        );
+        // Dates look like ratings, but aren't
+        assert_eq!(parse_rating("Published: 5/10/2023"), None);
+        assert_eq!(parse_rating("3/4 cup of flour"), None);
+        assert_eq!(parse_rating("Published: November 11, 2020"), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("Duration: 3:45"), Some(3 * 60 + 45));
+        assert_eq!(parse_duration("1:02:03 · YouTube"), Some(3723));
+        assert_eq!(parse_duration("Opens at 10:30 am"), None);
+        assert_eq!(parse_duration("November 11, 2020"), None);
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(parse_replies("12 replies · Top answer"), Some(12));
+        assert_eq!(parse_replies("1 answer"), Some(1));
+        assert_eq!(parse_replies("Posts: 2.5K"), Some(2500));
+        assert_eq!(parse_replies("Published: November 11, 2020"), None);
+    }
+
+    #[test]
+    fn sitelinks_are_resolved_and_deduplicated() {
+        let links = vec![
+            ("Docs".to_string(), "/docs".to_string()),
+            (
+                "Docs again".to_string(),
+                "https://example.com/docs".to_string(),
+            ),
+            ("Home".to_string(), "https://example.com/".to_string()),
+            (String::new(), "/empty".to_string()),
+        ];
+
+        assert_eq!(
+            collect_sitelinks("https://example.com/", links),
+            [Sitelink {
+                title: "Docs".to_string(),
+                url: "https://example.com/docs".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn brave_extras() {
+        let results = parse(Brave::new(), BRAVE_EXAMPLE);
+        let wikipedia = results
+            .iter()
+            .find(|result| result.url == "https://en.wikipedia.org/wiki/Test")
+            .unwrap();
+
+        assert_eq!(wikipedia.extras.breadcrumbs, ["wiki", "Test"]);
+        assert_eq!(
+            wikipedia.extras.sitelinks.first(),
+            Some(&Sitelink {
+                title: "Arts and entertainment".to_string(),
+                url: "https://en.wikipedia.org/wiki/Test#Arts_and_entertainment".to_string()
+            })
+        );
+        assert!(results
+            .iter()
+            .filter(|result| result.url != wikipedia.url)
+            .all(|result| result.extras.sitelinks.is_empty()));
+    }
+
+    #[test]
+    fn bing_extras() {
+        let results = parse(Bing::new(), BING_RESPONSE.as_bytes());
+        let [result] = results.as_slice() else {
+            panic!("expected a single result, got {:?}", results);
+        };
+
+        assert_eq!(result.title, "Thread & answers");
+        assert_eq!(result.description, "A description");
+        assert!(result.date.is_some());
+        assert_eq!(result.extras.breadcrumbs, ["forum", "thread"]);
+        assert_eq!(
+            result.extras.rating,
+            Some(Rating {
+                value: 45,
+                scale: 5,
+                count: Some(120)
+            })
+        );
+        assert_eq!(result.extras.replies, Some(12));
+        assert_eq!(
+            result.extras.sitelinks,
+            [
+                Sitelink {
+                    title: "Forum".to_string(),
+                    url: "https://www.example.com/forum".to_string()
+                },
+                Sitelink {
+                    title: "New & hot".to_string(),
+                    url: "https://www.example.com/forum/new".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn bing_sitelinks_split_across_packets() {
+        let expected = parse(Bing::new(), BING_RESPONSE.as_bytes());
+        let response = BING_RESPONSE.as_bytes();
+
+        // Packets ending inside the list of sitelinks used to end the result early
+        for offset in 1..response.len() {
+            let mut parser = Bing::new();
+            let (first, second) = response.split_at(offset);
+
+            parser.push_packet(first);
+            let mut results = std::iter::from_fn(|| parser.parse_next()).collect::<Vec<_>>();
+            parser.push_packet(second);
+            results.extend(std::iter::from_fn(|| parser.parse_next()));
+
+            assert_eq!(results, expected, "split at {}", offset);
+        }
+    }
+
+    #[test]
+    fn placeholders_in_results_are_rendered_as_text() {
+        let mut result = search_result(SearchEngine::Brave, "https://example.com/", 1);
+        result.title = "{% description %} and __ID__".to_string();
+        result.description = "{% url %}".to_string();
+        result.extras.breadcrumbs = vec!["{% title %}".to_string()];
+
+        let html = render_result(&result, &[SearchEngine::Brave]);
+
+        assert!(html.contains("{% description %} and __ID__"), "{}", html);
+        assert!(html.contains("{% url %}"), "{}", html);
+        assert!(html.contains("{% title %}"), "{}", html);
+    }
+}
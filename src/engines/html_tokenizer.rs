@@ -0,0 +1,522 @@
+// Incremental HTML tokenizer for search engine responses.
+// Text can be fed in arbitrary pieces; tokens are only returned once they are complete,
+// so a tag split across two packets is returned after the second one arrives.
+pub mod html_tokenizer {
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Token {
+        StartTag {
+            /// Lowercased tag name
+            name: String,
+            /// Lowercased attribute names with their raw, undecoded values
+            attributes: Vec<(String, String)>,
+            self_closing: bool,
+        },
+        EndTag {
+            name: String,
+        },
+        /// Raw, undecoded text. A single text node may be split into several tokens.
+        Text(String),
+    }
+
+    /// Elements whose content is never parsed as HTML. Their content is skipped.
+    /// Within SVG and MathML, they are parsed like any other element (e.g. `<title>` of icons).
+    const RAW_TEXT_ELEMENTS: [&str; 4] = ["script", "style", "textarea", "title"];
+    /// Elements whose content is foreign content
+    const FOREIGN_ELEMENTS: [&str; 2] = ["svg", "math"];
+    /// Bytes after a `>` within a quoted attribute value after which the quote is taken
+    /// as unterminated, so that it doesn't swallow the rest of the response
+    const UNTERMINATED_QUOTE_LIMIT: usize = 1024;
+
+    #[derive(Clone, Debug, Default)]
+    pub struct HtmlTokenizer {
+        buffer: String,
+        position: usize,
+        // Name of the raw text element whose end tag is searched for
+        raw_text_element: Option<String>,
+        // Number of open SVG and MathML elements
+        foreign_depth: usize,
+    }
+
+    impl HtmlTokenizer {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn feed(&mut self, text: &str) {
+            // Only the unfinished token at the end is left, so this stays cheap
+            self.buffer.drain(..self.position);
+            self.position = 0;
+            self.buffer.push_str(text);
+        }
+
+        /// Next complete token, or `None` if more input is needed
+        pub fn next_token(&mut self) -> Option<Token> {
+            loop {
+                if self.raw_text_element.is_some() {
+                    if !self.skip_raw_text() {
+                        return None;
+                    }
+
+                    continue;
+                }
+
+                let rest = &self.buffer[self.position..];
+
+                if rest.is_empty() {
+                    return None;
+                }
+
+                if !rest.starts_with('<') {
+                    let end = rest.find('<').unwrap_or(rest.len());
+                    let text = rest[..end].to_string();
+                    self.position += end;
+
+                    return Some(Token::Text(text));
+                }
+
+                match rest.as_bytes().get(1) {
+                    None => return None,
+                    Some(b'!') => {
+                        if !self.skip_markup_declaration() {
+                            return None;
+                        }
+                    }
+                    Some(b'?') => {
+                        if !self.skip_past(">") {
+                            return None;
+                        }
+                    }
+                    Some(b'/') => match rest.as_bytes().get(2) {
+                        None => return None,
+                        Some(next) if next.is_ascii_alphabetic() => {
+                            return self.read_end_tag();
+                        }
+                        // Bogus comment such as `</ >`
+                        Some(_) => {
+                            if !self.skip_past(">") {
+                                return None;
+                            }
+                        }
+                    },
+                    Some(next) if next.is_ascii_alphabetic() => {
+                        return self.read_start_tag();
+                    }
+                    // A `<` that doesn't start a tag is just text
+                    Some(_) => {
+                        self.position += 1;
+
+                        return Some(Token::Text("<".to_string()));
+                    }
+                }
+            }
+        }
+
+        /// Move past the next `sequence`. Returns false if it hasn't arrived yet.
+        fn skip_past(&mut self, sequence: &str) -> bool {
+            match self.buffer[self.position..].find(sequence) {
+                Some(index) => {
+                    self.position += index + sequence.len();
+
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Skip comments, doctypes and CDATA sections
+        fn skip_markup_declaration(&mut self) -> bool {
+            let rest = &self.buffer[self.position..];
+
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                // `-->` is searched after the opening so that `<!-->` isn't taken as closed
+                return match comment.find("-->") {
+                    Some(index) => {
+                        self.position += 4 + index + 3;
+
+                        true
+                    }
+                    None => false,
+                };
+            }
+
+            // Wait until it's clear whether this is a comment
+            if "<!--".starts_with(rest) {
+                return false;
+            }
+
+            self.skip_past(">")
+        }
+
+        /// Skip the content of a raw text element up to its end tag.
+        /// Returns false if the end tag hasn't arrived yet.
+        fn skip_raw_text(&mut self) -> bool {
+            let Some(element) = &self.raw_text_element else {
+                return true;
+            };
+
+            let end_tag = format!("</{}", element);
+            let rest = &self.buffer[self.position..];
+
+            match rest.to_ascii_lowercase().find(&end_tag) {
+                Some(index) => {
+                    self.position += index;
+                    self.raw_text_element = None;
+
+                    true
+                }
+                None => {
+                    // Keep enough of the end so that an end tag split across packets is found
+                    let mut keep_from = rest.len().saturating_sub(end_tag.len());
+
+                    while !rest.is_char_boundary(keep_from) {
+                        keep_from -= 1;
+                    }
+
+                    self.position += keep_from;
+
+                    false
+                }
+            }
+        }
+
+        /// Index of the `>` that closes the tag at the current position, ignoring
+        /// any `>` inside quoted attribute values unless the quote is unterminated
+        fn find_tag_end(&self) -> Option<usize> {
+            let mut quote: Option<u8> = None;
+            // First `>` within the current quoted value
+            let mut quoted_end: Option<usize> = None;
+            let mut previous = b'<';
+
+            for (index, byte) in self.buffer[self.position..].bytes().enumerate() {
+                match quote {
+                    Some(open) if byte == open => {
+                        quote = None;
+                        quoted_end = None;
+                    }
+                    Some(_) => match quoted_end {
+                        Some(end) if index - end >= UNTERMINATED_QUOTE_LIMIT => return Some(end),
+                        Some(_) => {}
+                        None if byte == b'>' => quoted_end = Some(index),
+                        None => {}
+                    },
+                    // Quotes only start attribute values directly after `=`
+                    None if (byte == b'"' || byte == b'\'') && previous == b'=' => {
+                        quote = Some(byte)
+                    }
+                    None if byte == b'>' => return Some(index),
+                    None => {}
+                }
+
+                if !byte.is_ascii_whitespace() {
+                    previous = byte;
+                }
+            }
+
+            None
+        }
+
+        fn read_end_tag(&mut self) -> Option<Token> {
+            let end = self.find_tag_end()?;
+            let content = &self.buffer[self.position + 2..self.position + end];
+            let name = content
+                .split(|c: char| c.is_ascii_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+
+            self.position += end + 1;
+
+            if FOREIGN_ELEMENTS.contains(&name.as_str()) {
+                self.foreign_depth = self.foreign_depth.saturating_sub(1);
+            }
+
+            Some(Token::EndTag { name })
+        }
+
+        fn read_start_tag(&mut self) -> Option<Token> {
+            let end = self.find_tag_end()?;
+            let content = &self.buffer[self.position + 1..self.position + end];
+            let self_closing = content.ends_with('/');
+            let (name, attributes) = parse_tag_content(content);
+
+            self.position += end + 1;
+
+            if !self_closing {
+                if FOREIGN_ELEMENTS.contains(&name.as_str()) {
+                    self.foreign_depth += 1;
+                } else if RAW_TEXT_ELEMENTS.contains(&name.as_str()) && self.foreign_depth == 0 {
+                    self.raw_text_element = Some(name.clone());
+                }
+            }
+
+            Some(Token::StartTag {
+                name,
+                attributes,
+                self_closing,
+            })
+        }
+    }
+
+    /// Split the content of a start tag, such as `a class="x" href=y`,
+    /// into its name and attributes
+    fn parse_tag_content(content: &str) -> (String, Vec<(String, String)>) {
+        let is_name_end = |c: char| c.is_ascii_whitespace() || c == '/' || c == '=';
+
+        let name_end = content.find(is_name_end).unwrap_or(content.len());
+        let name = content[..name_end].to_ascii_lowercase();
+        let mut attributes = Vec::new();
+        let mut rest = &content[name_end..];
+
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+
+            if rest.is_empty() {
+                break;
+            }
+
+            // The first character is part of the name, even if it is a `=`
+            let attribute_end = rest
+                .char_indices()
+                .skip(1)
+                .find(|(_, c)| is_name_end(*c))
+                .map_or(rest.len(), |(index, _)| index);
+            let attribute = rest[..attribute_end].to_ascii_lowercase();
+            rest = rest[attribute_end..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+            let value = match rest.strip_prefix('=') {
+                Some(after_equals) => {
+                    let after_equals =
+                        after_equals.trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+                    match after_equals.chars().next() {
+                        Some(quote @ ('"' | '\'')) => {
+                            let value_end = after_equals[1..]
+                                .find(quote)
+                                .map_or(after_equals.len(), |index| index + 1);
+                            rest = after_equals.get(value_end + 1..).unwrap_or_default();
+
+                            &after_equals[1..value_end]
+                        }
+                        _ => {
+                            let value_end = after_equals
+                                .find(|c: char| c.is_ascii_whitespace())
+                                .unwrap_or(after_equals.len());
+                            rest = &after_equals[value_end..];
+
+                            &after_equals[..value_end]
+                        }
+                    }
+                }
+                None => "",
+            };
+
+            attributes.push((attribute, value.to_string()));
+        }
+
+        (name, attributes)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Tokens of `pieces` fed one after another, with adjacent text merged
+        fn tokenize(pieces: &[&str]) -> Vec<Token> {
+            let mut tokenizer = HtmlTokenizer::new();
+            let mut tokens = Vec::new();
+
+            for piece in pieces {
+                tokenizer.feed(piece);
+
+                while let Some(token) = tokenizer.next_token() {
+                    match (tokens.last_mut(), token) {
+                        (Some(Token::Text(previous)), Token::Text(text)) => {
+                            previous.push_str(&text)
+                        }
+                        (_, token) => tokens.push(token),
+                    }
+                }
+            }
+
+            tokens
+        }
+
+        fn start_tag(name: &str, attributes: &[(&str, &str)]) -> Token {
+            Token::StartTag {
+                name: name.to_string(),
+                attributes: attributes
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                self_closing: false,
+            }
+        }
+
+        fn end_tag(name: &str) -> Token {
+            Token::EndTag {
+                name: name.to_string(),
+            }
+        }
+
+        fn text(text: &str) -> Token {
+            Token::Text(text.to_string())
+        }
+
+        /// Check that `html` gives the same tokens however it is split in two
+        fn assert_split_anywhere(html: &str) {
+            let whole = tokenize(&[html]);
+
+            for (offset, _) in html.char_indices().skip(1) {
+                let (first, second) = html.split_at(offset);
+
+                assert_eq!(tokenize(&[first, second]), whole, "split at {}", offset);
+            }
+        }
+
+        #[test]
+        fn tags_and_attributes() {
+            assert_eq!(
+                tokenize(&[r#"<A Href="/a?b=1&amp;c" class='x > y' data-id=3 hidden>Link</a >"#]),
+                vec![
+                    start_tag(
+                        "a",
+                        &[
+                            ("href", "/a?b=1&amp;c"),
+                            ("class", "x > y"),
+                            ("data-id", "3"),
+                            ("hidden", "")
+                        ]
+                    ),
+                    text("Link"),
+                    end_tag("a"),
+                ]
+            );
+            assert_eq!(
+                tokenize(&["<br/><img src=x.png />"]),
+                vec![
+                    Token::StartTag {
+                        name: "br".to_string(),
+                        attributes: Vec::new(),
+                        self_closing: true,
+                    },
+                    Token::StartTag {
+                        name: "img".to_string(),
+                        attributes: vec![("src".to_string(), "x.png".to_string())],
+                        self_closing: true,
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn tags_split_across_packets() {
+            assert_eq!(
+                tokenize(&["<di", "v cla", "ss=\"res", "ult\"", ">Te", "xt</", "div>"]),
+                vec![
+                    start_tag("div", &[("class", "result")]),
+                    text("Text"),
+                    end_tag("div"),
+                ]
+            );
+
+            assert_split_anywhere(
+                r#"<!DOCTYPE html><!-- a <b> comment --><p class="a>b" id=x>Ünïcödé 🦀</p><?xml?>"#,
+            );
+        }
+
+        #[test]
+        fn raw_text_elements_are_skipped() {
+            let html = r#"<script>if (a < b && "</div>") {}</script ><style>p::after { content: "<p>" }</STYLE><p>Text</p>"#;
+
+            assert_eq!(
+                tokenize(&[html]),
+                vec![
+                    start_tag("script", &[]),
+                    end_tag("script"),
+                    start_tag("style", &[]),
+                    end_tag("style"),
+                    start_tag("p", &[]),
+                    text("Text"),
+                    end_tag("p"),
+                ]
+            );
+            assert_split_anywhere(html);
+        }
+
+        #[test]
+        fn malformed_markup() {
+            assert_eq!(
+                tokenize(&["a < b <3 </ > c</p x=1><!-- <a> --->d<!---->"]),
+                vec![text("a < b <3  c"), end_tag("p"), text("d")]
+            );
+            assert_eq!(
+                tokenize(&[r#"<a href=x"y title=>z"#]),
+                vec![
+                    start_tag("a", &[("href", "x\"y"), ("title", "")]),
+                    text("z")
+                ]
+            );
+        }
+
+        #[test]
+        fn unterminated_quotes() {
+            // The quote could still be closed
+            assert_eq!(tokenize(&[r#"<a href="x>y</a>"#]), Vec::<Token>::new());
+
+            // Far enough after the `>`, it is taken as the end of the tag
+            let padding = "z".repeat(UNTERMINATED_QUOTE_LIMIT);
+            let html = format!(r#"<a href="x>y</a><p>{}</p>"#, padding);
+
+            assert_eq!(
+                tokenize(&[&html]),
+                vec![
+                    start_tag("a", &[("href", "x")]),
+                    text("y"),
+                    end_tag("a"),
+                    start_tag("p", &[]),
+                    text(&padding),
+                    end_tag("p"),
+                ]
+            );
+            assert_split_anywhere(&html);
+
+            // Quoted values may contain `>` as long as they are closed soon after it
+            let long_value = format!("a > {}", "z".repeat(UNTERMINATED_QUOTE_LIMIT / 2));
+            assert_eq!(
+                tokenize(&[&format!(r#"<a title="{}">"#, long_value)]),
+                vec![start_tag("a", &[("title", &long_value)])]
+            );
+        }
+
+        #[test]
+        fn foreign_content() {
+            let html = r#"<title>A <b>title</b></title><svg><title>Icon</title><svg/><path d="M0"/></svg><title><i></title>"#;
+
+            assert_eq!(
+                tokenize(&[html]),
+                vec![
+                    start_tag("title", &[]),
+                    end_tag("title"),
+                    start_tag("svg", &[]),
+                    start_tag("title", &[]),
+                    text("Icon"),
+                    end_tag("title"),
+                    Token::StartTag {
+                        name: "svg".to_string(),
+                        attributes: Vec::new(),
+                        self_closing: true,
+                    },
+                    Token::StartTag {
+                        name: "path".to_string(),
+                        attributes: vec![("d".to_string(), "M0".to_string())],
+                        self_closing: true,
+                    },
+                    end_tag("svg"),
+                    start_tag("title", &[]),
+                    end_tag("title"),
+                ]
+            );
+            assert_split_anywhere(html);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+// Benchmarks of the engine parsers using the example responses.
+//
+// Timings depend on the machine, so the parsers are only compared to themselves:
+// parsing twice as much must take about twice as long, and the first result must
+// arrive before the response could have been parsed as a whole.
+//
+// Wall-clock timings are slow and noisy on shared machines, so all benchmarks are
+// ignored by default, run them using:
+//
+// cargo test --release parser_benchmarks -- --ignored --nocapture
+pub mod parser_benchmarks {
+    use std::time::{Duration, Instant};
+
+    use crate::engines::{
+        bing::bing::Bing,
+        brave::brave::Brave,
+        duckduckgo::duckduckgo::DuckDuckGo,
+        engine_base::engine_base::EngineBase,
+        registry::registry::Engine,
+        test_fixtures::test_fixtures::{
+            configured_engine, NewParser, BING_EXAMPLE, BRAVE_EXAMPLE, DUCKDUCKGO_EXAMPLE,
+            MOJEEK_EXAMPLE, MOJEEK_REGEX_ENGINE, MOJEEK_SELECTOR_ENGINE,
+        },
+    };
+
+    // A typical TCP payload, a large read buffer and the whole response at once
+    const PACKET_SIZES: [usize; 3] = [1460, 16 * 1024, usize::MAX];
+    const TCP_PACKET_SIZE: usize = PACKET_SIZES[0];
+    const RUNS: u32 = 20;
+    // Large result pages are simulated by repeating an example
+    const LARGE_PAGE_REPETITIONS: usize = 20;
+    // Parsing twice as much may take this many times as long; 2 if perfectly linear,
+    // 4 if quadratic
+    const MAX_DOUBLING_FACTOR: f64 = 3.0;
+    // Markup after the last result that never matches, e.g. a long footer
+    const TAIL_ELEMENT: &str = r#"<li class="footer"><a href="/about">About</a></li>"#;
+    const TAIL_LENGTH: usize = 256 * 1024;
+
+    struct Measurement {
+        first_result: Duration,
+        total: Duration,
+        count: usize,
+    }
+
+    fn measure(
+        mut parser: Box<dyn EngineBase>,
+        response: &[u8],
+        packet_size: usize,
+    ) -> Measurement {
+        let start = Instant::now();
+        let mut first_result = None;
+        let mut count = 0;
+
+        for packet in response.chunks(packet_size) {
+            parser.push_packet(packet);
+
+            while parser.parse_next().is_some() {
+                count += 1;
+                first_result.get_or_insert_with(|| start.elapsed());
+            }
+        }
+
+        let total = start.elapsed();
+
+        Measurement {
+            first_result: first_result.unwrap_or(total),
+            total,
+            count,
+        }
+    }
+
+    /// Average of `RUNS` measurements
+    fn average(
+        new_parser: &impl Fn() -> Box<dyn EngineBase>,
+        response: &[u8],
+        packet_size: usize,
+    ) -> Measurement {
+        let mut first_result = Duration::ZERO;
+        let mut total = Duration::ZERO;
+        let mut count = 0;
+
+        for _ in 0..RUNS {
+            let measurement = measure(new_parser(), response, packet_size);
+            first_result += measurement.first_result;
+            total += measurement.total;
+            count = measurement.count;
+        }
+
+        Measurement {
+            first_result: first_result / RUNS,
+            total: total / RUNS,
+            count,
+        }
+    }
+
+    /// Print the timings for every packet size, and check that streaming pays off
+    fn report(name: &str, new_parser: impl Fn() -> Box<dyn EngineBase>, response: &[u8]) {
+        let measurements = PACKET_SIZES.map(|packet_size| {
+            let measurement = average(&new_parser, response, packet_size);
+
+            let packet_size = match packet_size {
+                usize::MAX => "one packet".to_string(),
+                size => format!("packets of {} bytes", size),
+            };
+
+            println!(
+                "{} ({} KiB, {}): {} results, first after {:?}, all after {:?}",
+                name,
+                response.len() / 1024,
+                packet_size,
+                measurement.count,
+                measurement.first_result,
+                measurement.total,
+            );
+
+            measurement
+        });
+
+        let [packets, .., whole] = &measurements;
+
+        assert_eq!(packets.count, whole.count, "{}", name);
+        assert!(
+            packets.first_result < whole.total,
+            "{}: the first result took longer than parsing the whole response",
+            name
+        );
+    }
+
+    /// Fastest of a few runs in TCP sized packets, which is the least disturbed by other tests
+    fn fastest(new_parser: &impl Fn() -> Box<dyn EngineBase>, response: &[u8]) -> Duration {
+        (0..3)
+            .map(|_| measure(new_parser(), response, TCP_PACKET_SIZE).total)
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Check that parsing `large`, which is twice as long as `small`, takes about twice as long
+    fn assert_linear(
+        name: &str,
+        new_parser: impl Fn() -> Box<dyn EngineBase>,
+        small: &[u8],
+        large: &[u8],
+    ) {
+        let small = fastest(&new_parser, small);
+        let large = fastest(&new_parser, large);
+        let factor = large.as_secs_f64() / small.as_secs_f64().max(f64::EPSILON);
+
+        assert!(
+            factor < MAX_DOUBLING_FACTOR,
+            "{}: parsing twice as much took {:.1} times as long ({:?} and {:?})",
+            name,
+            factor,
+            small,
+            large
+        );
+    }
+
+    /// `example` followed by `length` bytes of markup without results
+    fn with_tail(example: &[u8], length: usize) -> Vec<u8> {
+        let tail = TAIL_ELEMENT.repeat(length / TAIL_ELEMENT.len());
+
+        [example, tail.as_bytes()].concat()
+    }
+
+    #[test]
+    #[ignore]
+    fn linear_in_the_number_of_results() {
+        let mojeek_regex = configured_engine(MOJEEK_REGEX_ENGINE);
+        let mojeek_selectors = configured_engine(MOJEEK_SELECTOR_ENGINE);
+        let parsers: [(&str, &[u8], NewParser<'_>); 4] = [
+            ("Bing", BING_EXAMPLE, Box::new(|| Box::new(Bing::new()))),
+            (
+                "DuckDuckGo",
+                DUCKDUCKGO_EXAMPLE,
+                Box::new(|| Box::new(DuckDuckGo::new())),
+            ),
+            (
+                "Mojeek (regex)",
+                MOJEEK_EXAMPLE,
+                Box::new(|| mojeek_regex.parser()),
+            ),
+            (
+                "Mojeek (selectors)",
+                MOJEEK_EXAMPLE,
+                Box::new(|| mojeek_selectors.parser()),
+            ),
+        ];
+
+        for (name, example, new_parser) in parsers {
+            assert_linear(name, new_parser, &example.repeat(4), &example.repeat(8));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn linear_in_the_length_of_the_tail() {
+        let mojeek_regex = configured_engine(MOJEEK_REGEX_ENGINE);
+        let mojeek_selectors = configured_engine(MOJEEK_SELECTOR_ENGINE);
+        let small = with_tail(MOJEEK_EXAMPLE, TAIL_LENGTH);
+        let large = with_tail(MOJEEK_EXAMPLE, 2 * TAIL_LENGTH);
+
+        assert_linear("Mojeek (regex)", || mojeek_regex.parser(), &small, &large);
+        assert_linear(
+            "Mojeek (selectors)",
+            || mojeek_selectors.parser(),
+            &small,
+            &large,
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn time_to_first_result() {
+        report("Brave", || Box::new(Brave::new()), BRAVE_EXAMPLE);
+        report(
+            "DuckDuckGo",
+            || Box::new(DuckDuckGo::new()),
+            DUCKDUCKGO_EXAMPLE,
+        );
+        report("Bing", || Box::new(Bing::new()), BING_EXAMPLE);
+    }
+
+    #[test]
+    #[ignore]
+    fn large_result_pages() {
+        let half = LARGE_PAGE_REPETITIONS / 2;
+
+        report(
+            "Brave",
+            || Box::new(Brave::new()),
+            &BRAVE_EXAMPLE.repeat(LARGE_PAGE_REPETITIONS),
+        );
+        assert_linear(
+            "Brave",
+            || Box::new(Brave::new()),
+            &BRAVE_EXAMPLE.repeat(half),
+            &BRAVE_EXAMPLE.repeat(LARGE_PAGE_REPETITIONS),
+        );
+
+        report(
+            "DuckDuckGo",
+            || Box::new(DuckDuckGo::new()),
+            &DUCKDUCKGO_EXAMPLE.repeat(LARGE_PAGE_REPETITIONS),
+        );
+        assert_linear(
+            "DuckDuckGo",
+            || Box::new(DuckDuckGo::new()),
+            &DUCKDUCKGO_EXAMPLE.repeat(half),
+            &DUCKDUCKGO_EXAMPLE.repeat(LARGE_PAGE_REPETITIONS),
+        );
+    }
+}
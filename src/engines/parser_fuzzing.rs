@@ -0,0 +1,242 @@
+// Tests that the engine parsers return the same results, no matter how a response
+// is split into packets.
+//
+// Small fixtures are split at every single offset by default. Doing so for the large
+// ones takes several minutes, so that test is ignored by default, run it using:
+//
+// cargo test --release parser_fuzzing -- --ignored
+pub mod parser_fuzzing {
+    use crate::engines::{
+        bing::bing::Bing,
+        brave::brave::Brave,
+        duckduckgo::duckduckgo::DuckDuckGo,
+        engine_base::engine_base::SearchResult,
+        registry::registry::Engine,
+        test_fixtures::test_fixtures::{
+            comparable, configured_engine, NewParser, BING_EXAMPLE, BRAVE_EXAMPLE,
+            DUCKDUCKGO_EXAMPLE, MOJEEK_EXAMPLE, MOJEEK_REGEX_ENGINE, MOJEEK_SELECTOR_ENGINE,
+        },
+    };
+
+    const SPLIT_OFFSETS: usize = 200;
+    const RANDOM_SPLITS: usize = 100;
+    const MAX_RANDOM_SPLIT_POINTS: usize = 20;
+    // Fixtures up to this size are split at every offset by default
+    const SMALL_FIXTURE_SIZE: usize = 8 * 1024;
+
+    struct Fixture {
+        name: &'static str,
+        response: &'static [u8],
+        new_parser: NewParser<'static>,
+    }
+
+    /// Parsers of the first engine defined in `config`
+    fn configured_parser(config: &str) -> NewParser<'static> {
+        let engine = configured_engine(config);
+
+        Box::new(move || engine.parser())
+    }
+
+    fn fixtures() -> [Fixture; 5] {
+        [
+            Fixture {
+                name: "Brave",
+                response: BRAVE_EXAMPLE,
+                new_parser: Box::new(|| Box::new(Brave::new())),
+            },
+            Fixture {
+                name: "DuckDuckGo",
+                response: DUCKDUCKGO_EXAMPLE,
+                new_parser: Box::new(|| Box::new(DuckDuckGo::new())),
+            },
+            Fixture {
+                name: "Bing",
+                response: BING_EXAMPLE,
+                new_parser: Box::new(|| Box::new(Bing::new())),
+            },
+            Fixture {
+                name: "Mojeek (regex)",
+                response: MOJEEK_EXAMPLE,
+                new_parser: configured_parser(MOJEEK_REGEX_ENGINE),
+            },
+            Fixture {
+                name: "Mojeek (selectors)",
+                response: MOJEEK_EXAMPLE,
+                new_parser: configured_parser(MOJEEK_SELECTOR_ENGINE),
+            },
+        ]
+    }
+
+    /// Small deterministic pseudo random number generator (xorshift64),
+    /// so that failures can be reproduced
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+
+            self.0
+        }
+
+        fn below(&mut self, max: usize) -> usize {
+            (self.next() % max as u64) as usize
+        }
+    }
+
+    /// Parse `response` split at `offsets`, which must be sorted
+    fn parse_split(fixture: &Fixture, offsets: &[usize]) -> Vec<SearchResult> {
+        let mut parser = (fixture.new_parser)();
+        let mut results = Vec::new();
+        let mut start = 0;
+
+        for end in offsets.iter().copied().chain([fixture.response.len()]) {
+            parser.push_packet(&fixture.response[start..end]);
+            start = end;
+
+            while let Some(result) = parser.parse_next() {
+                results.push(comparable(result));
+            }
+        }
+
+        results
+    }
+
+    fn assert_same_results(fixture: &Fixture, offsets: &[usize], expected: &[SearchResult]) {
+        let results = parse_split(fixture, offsets);
+
+        assert!(
+            results == expected,
+            "{}: different results when split at {:?}:\n{:#?}\nexpected:\n{:#?}",
+            fixture.name,
+            offsets,
+            results,
+            expected,
+        );
+    }
+
+    #[test]
+    fn single_chunk_has_results() {
+        for fixture in fixtures() {
+            assert!(
+                !parse_split(&fixture, &[]).is_empty(),
+                "{}: no results",
+                fixture.name
+            );
+        }
+    }
+
+    #[test]
+    fn hand_written_fixtures() {
+        let [_, _, bing, mojeek_regex, mojeek_selectors] = fixtures();
+
+        // Ads, answers and the strings in scripts aren't results
+        let results = parse_split(&bing, &[]);
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.url.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "https://www.rust-lang.org/",
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+                "https://stackoverflow.com/questions/tagged/rust",
+                "https://www.rust-lang.org/learn",
+                "https://github.com/rust-lang/rust",
+                "https://www.reddit.com/r/rust/",
+            ]
+        );
+        assert_eq!(results[0].extras.sitelinks.len(), 3);
+        assert_eq!(
+            results[0].extras.sitelinks[2].url,
+            "https://doc.rust-lang.org/book/"
+        );
+        assert!(results[1].date.is_some() && results[2].date.is_some());
+
+        // The link that isn't a web page is skipped
+        for fixture in [mojeek_regex, mojeek_selectors] {
+            let results = parse_split(&fixture, &[]);
+
+            assert_eq!(results.len(), 7, "{}", fixture.name);
+            assert_eq!(
+                results[4].url, "https://www.mojeek.com/about/why-mojeek",
+                "{}",
+                fixture.name
+            );
+        }
+    }
+
+    #[test]
+    fn split_at_offsets() {
+        for fixture in fixtures() {
+            let expected = parse_split(&fixture, &[]);
+            let step = (fixture.response.len() / SPLIT_OFFSETS).max(1);
+
+            for offset in (1..fixture.response.len()).step_by(step) {
+                assert_same_results(&fixture, &[offset], &expected);
+            }
+        }
+    }
+
+    fn split_at_every_offset(fixture: &Fixture) {
+        let expected = parse_split(fixture, &[]);
+
+        for offset in 1..fixture.response.len() {
+            assert_same_results(fixture, &[offset], &expected);
+        }
+    }
+
+    #[test]
+    fn split_small_fixtures_at_every_offset() {
+        for fixture in fixtures() {
+            if fixture.response.len() <= SMALL_FIXTURE_SIZE {
+                split_at_every_offset(&fixture);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn split_large_fixtures_at_every_offset() {
+        for fixture in fixtures() {
+            if fixture.response.len() > SMALL_FIXTURE_SIZE {
+                split_at_every_offset(&fixture);
+            }
+        }
+    }
+
+    #[test]
+    fn split_into_small_packets() {
+        for fixture in fixtures() {
+            let expected = parse_split(&fixture, &[]);
+
+            // Splitting into single bytes splits every tag, word and character
+            for packet_size in 1..=8 {
+                let offsets = (packet_size..fixture.response.len())
+                    .step_by(packet_size)
+                    .collect::<Vec<_>>();
+
+                assert_same_results(&fixture, &offsets, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn split_at_random_offsets() {
+        let mut random = Random(0x5EED_CAFE_F00D_D00D);
+
+        for fixture in fixtures() {
+            let expected = parse_split(&fixture, &[]);
+
+            for _ in 0..RANDOM_SPLITS {
+                let mut offsets = (0..1 + random.below(MAX_RANDOM_SPLIT_POINTS))
+                    .map(|_| 1 + random.below(fixture.response.len() - 1))
+                    .collect::<Vec<_>>();
+                offsets.sort();
+
+                assert_same_results(&fixture, &offsets, &expected);
+            }
+        }
+    }
+}
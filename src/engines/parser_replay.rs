@@ -0,0 +1,248 @@
+// End-to-end tests of `handle_request` using responses served by the replay server
+pub mod parser_replay {
+    use std::{fs, path::Path};
+
+    use chrono::Utc;
+    use tokio::sync::mpsc;
+
+    use crate::{
+        engines::{
+            brave::brave::Brave,
+            drift::drift::{DriftConfig, ParserDriftMonitor},
+            engine_base::engine_base::{
+                EngineBase, EngineError, SearchEngine, SearchEvent, SearchResult,
+            },
+            registry::registry::Engine,
+            test_fixtures::test_fixtures::{comparable, temporary_directory, BRAVE_EXAMPLE},
+        },
+        helpers::helpers::build_default_client,
+        recording::recording::{
+            fixture_name, load_fixture, save_fixture, start_replay_server, RecordedChunk,
+            RecordedResponse, ResponseRecording, ResponseTransport,
+        },
+    };
+
+    const QUERY: &str = "test";
+    const PACKET_SIZE: usize = 1460;
+
+    fn save_response(directory: &Path, query: &str, body: &[u8]) {
+        save_fixture(
+            directory,
+            &RecordedResponse {
+                engine: SearchEngine::Brave,
+                query: query.to_string(),
+                url: "https://search.brave.com/search".to_string(),
+                status: 200,
+                recorded_at: Utc::now(),
+                headers_ms: 0,
+                chunks: vec![RecordedChunk {
+                    end: body.len(),
+                    elapsed_ms: 0,
+                }],
+            },
+            body,
+        )
+        .unwrap();
+    }
+
+    /// Save the Brave example as if it had arrived in packets of `PACKET_SIZE`, 1ms apart
+    fn save_brave_example(directory: &Path) {
+        let chunks = (1..=BRAVE_EXAMPLE.len().div_ceil(PACKET_SIZE))
+            .map(|index| RecordedChunk {
+                end: (index * PACKET_SIZE).min(BRAVE_EXAMPLE.len()),
+                elapsed_ms: index as u64,
+            })
+            .collect();
+
+        save_fixture(
+            directory,
+            &RecordedResponse {
+                engine: SearchEngine::Brave,
+                query: QUERY.to_string(),
+                url: "https://search.brave.com/search?q=test".to_string(),
+                status: 200,
+                recorded_at: Utc::now(),
+                headers_ms: 0,
+                chunks,
+            },
+            BRAVE_EXAMPLE,
+        )
+        .unwrap();
+    }
+
+    /// Results of the example with the positions `search` gives them
+    fn parse_whole_example() -> Vec<SearchResult> {
+        let mut parser = Brave::new();
+        parser.push_packet(BRAVE_EXAMPLE);
+
+        std::iter::from_fn(|| parser.parse_next())
+            .zip(1..)
+            .map(|(result, position)| comparable(SearchResult { position, ..result }))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_response() {
+        let directory = temporary_directory("replay-replay");
+        save_brave_example(&directory);
+
+        let transport = ResponseTransport::Replay {
+            directory: directory.clone(),
+            address: start_replay_server(directory.clone()).await.unwrap(),
+        };
+        let (tx, mut rx) = mpsc::channel(256);
+
+        let count = Brave::new()
+            .search(QUERY, &transport, &ParserDriftMonitor::default(), tx)
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        assert!(matches!(
+            events.first(),
+            Some(SearchEvent::FirstByte { .. })
+        ));
+
+        let results = events
+            .into_iter()
+            .filter_map(|event| match event {
+                SearchEvent::Result(result) => Some(comparable(result)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(count, results.len());
+        assert_eq!(results, parse_whole_example());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn missing_recording_fails() {
+        let directory = temporary_directory("replay-missing");
+
+        let transport = ResponseTransport::Replay {
+            directory: directory.clone(),
+            address: start_replay_server(directory.clone()).await.unwrap(),
+        };
+        let (tx, _rx) = mpsc::channel(256);
+
+        let error = Brave::new()
+            .search(QUERY, &transport, &ParserDriftMonitor::default(), tx)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, EngineError::Network(_)));
+    }
+
+    #[tokio::test]
+    async fn records_replayed_response() {
+        let directory = temporary_directory("replay-record-source");
+        save_brave_example(&directory);
+        let recording_directory = temporary_directory("replay-record");
+
+        let address = start_replay_server(directory.clone()).await.unwrap();
+        let client = build_default_client();
+        let request = client.get(format!(
+            "http://{}/{}",
+            address,
+            fixture_name(&SearchEngine::Brave, QUERY)
+        ));
+        let recording =
+            ResponseRecording::new(recording_directory.clone(), SearchEngine::Brave, QUERY);
+        let (tx, mut rx) = mpsc::channel(256);
+
+        Brave::new()
+            .handle_request(SearchEngine::Brave, request, Some(recording), None, tx)
+            .await
+            .unwrap();
+        while rx.recv().await.is_some() {}
+
+        let (response, body) = load_fixture(
+            &recording_directory,
+            &fixture_name(&SearchEngine::Brave, QUERY),
+        )
+        .unwrap();
+
+        assert_eq!(body, BRAVE_EXAMPLE);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.query, QUERY);
+        assert!(!response.chunks.is_empty());
+
+        let _ = fs::remove_dir_all(&directory);
+        let _ = fs::remove_dir_all(&recording_directory);
+    }
+
+    #[test]
+    fn rejects_fixtures_with_invalid_chunks() {
+        let directory = temporary_directory("replay-invalid-chunks");
+        let body = b"<html><body>Results</body></html>";
+        let name = fixture_name(&SearchEngine::Brave, QUERY);
+
+        for ends in [vec![10, 5, body.len()], vec![10, body.len() + 1], vec![10]] {
+            save_fixture(
+                &directory,
+                &RecordedResponse {
+                    engine: SearchEngine::Brave,
+                    query: QUERY.to_string(),
+                    url: "https://search.brave.com/search?q=test".to_string(),
+                    status: 200,
+                    recorded_at: Utc::now(),
+                    headers_ms: 0,
+                    chunks: ends
+                        .iter()
+                        .map(|end| RecordedChunk {
+                            end: *end,
+                            elapsed_ms: 0,
+                        })
+                        .collect(),
+                },
+                body,
+            )
+            .unwrap();
+
+            assert!(
+                load_fixture(&directory, &name).is_err(),
+                "chunks {:?}",
+                ends
+            );
+        }
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn reports_responses_to_the_drift_monitor() {
+        let directory = temporary_directory("replay-drift");
+        save_response(
+            &directory,
+            "empty",
+            b"<html><body>Changed markup</body></html>",
+        );
+        save_brave_example(&directory);
+
+        let transport = ResponseTransport::Replay {
+            directory: directory.clone(),
+            address: start_replay_server(directory.clone()).await.unwrap(),
+        };
+        let drift = ParserDriftMonitor::new(DriftConfig {
+            max_dumps: 0,
+            ..DriftConfig::default()
+        });
+
+        for query in ["empty", QUERY, "empty"] {
+            let (tx, mut rx) = mpsc::channel(256);
+            let _ = Brave::new().search(query, &transport, &drift, tx).await;
+            while rx.recv().await.is_some() {}
+        }
+
+        let status = drift.status(&SearchEngine::Brave);
+        assert_eq!((status.responses, status.empty_responses), (3, 2));
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
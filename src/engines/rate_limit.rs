@@ -0,0 +1,236 @@
+// Outbound rate limiting, shared by all searches of this instance.
+// Each engine has a token bucket for its request rate and a semaphore
+// for the number of requests that may run at the same time.
+pub mod rate_limit {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use ahash::AHashMap;
+    use serde::Deserialize;
+    use tokio::{
+        sync::{OwnedSemaphorePermit, Semaphore},
+        time::{self, Instant},
+    };
+
+    use crate::engines::engine_base::engine_base::SearchEngine;
+
+    #[derive(Clone, Copy, Debug, Deserialize)]
+    #[serde(default)]
+    pub struct RateLimit {
+        pub requests_per_second: f64,
+        pub max_concurrency: usize,
+        /// How long a search may wait for the engine to be within budget
+        /// before the engine is skipped
+        pub max_wait_ms: u64,
+    }
+
+    impl Default for RateLimit {
+        fn default() -> Self {
+            Self {
+                requests_per_second: 5.0,
+                max_concurrency: 8,
+                max_wait_ms: 1000,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct TokenBucket {
+        capacity: f64,
+        tokens: f64,
+        refill_per_second: f64,
+        last_refill: Instant,
+    }
+
+    impl TokenBucket {
+        fn new(requests_per_second: f64) -> Self {
+            let capacity = requests_per_second.ceil().max(1.0);
+
+            Self {
+                capacity,
+                tokens: capacity,
+                refill_per_second: requests_per_second,
+                last_refill: Instant::now(),
+            }
+        }
+
+        /// Take a token and return how long to wait until it may be used.
+        /// Tokens can go negative, which queues the caller behind earlier ones.
+        fn reserve(&mut self, max_wait: Duration) -> Option<Duration> {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+            self.last_refill = now;
+
+            let wait = if self.tokens >= 1.0 {
+                Duration::ZERO
+            } else if self.refill_per_second <= 0.0 {
+                return None;
+            } else {
+                Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_second)
+            };
+
+            if wait > max_wait {
+                return None;
+            }
+
+            self.tokens -= 1.0;
+
+            Some(wait)
+        }
+    }
+
+    #[derive(Debug)]
+    struct EngineBudget {
+        limit: RateLimit,
+        bucket: Mutex<TokenBucket>,
+        concurrency: Arc<Semaphore>,
+    }
+
+    impl EngineBudget {
+        fn new(limit: RateLimit) -> Self {
+            Self {
+                limit,
+                bucket: Mutex::new(TokenBucket::new(limit.requests_per_second)),
+                concurrency: Arc::new(Semaphore::new(limit.max_concurrency.max(1))),
+            }
+        }
+    }
+
+    /// Held while a request to an engine is running
+    pub struct RateLimitPermit {
+        _permit: OwnedSemaphorePermit,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct RateLimiter {
+        default_limit: RateLimit,
+        limits: AHashMap<SearchEngine, RateLimit>,
+        budgets: Mutex<AHashMap<SearchEngine, Arc<EngineBudget>>>,
+    }
+
+    impl RateLimiter {
+        pub fn new(default_limit: RateLimit, limits: AHashMap<SearchEngine, RateLimit>) -> Self {
+            Self {
+                default_limit,
+                limits,
+                budgets: Mutex::new(AHashMap::new()),
+            }
+        }
+
+        fn budget(&self, engine: &SearchEngine) -> Arc<EngineBudget> {
+            self.budgets
+                .lock()
+                .unwrap()
+                .entry(*engine)
+                .or_insert_with(|| {
+                    Arc::new(EngineBudget::new(
+                        *self.limits.get(engine).unwrap_or(&self.default_limit),
+                    ))
+                })
+                .clone()
+        }
+
+        /// Wait until a request to `engine` is within its budget.
+        /// Returns `None` if that would take longer than the engine's `max_wait_ms`.
+        pub async fn acquire(&self, engine: &SearchEngine) -> Option<RateLimitPermit> {
+            let budget = self.budget(engine);
+            let deadline = Instant::now() + Duration::from_millis(budget.limit.max_wait_ms);
+
+            // The permit is taken before the token, so that a search that gives up
+            // waiting for a free slot doesn't use up a token it never spends
+            let permit = time::timeout_at(deadline, budget.concurrency.clone().acquire_owned())
+                .await
+                .ok()?
+                .ok()?;
+
+            let max_wait = deadline.saturating_duration_since(Instant::now());
+            let wait = budget.bucket.lock().unwrap().reserve(max_wait)?;
+            time::sleep(wait).await;
+
+            Some(RateLimitPermit { _permit: permit })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use ahash::AHashMap;
+        use tokio::time::Instant;
+
+        use super::{RateLimit, RateLimiter, TokenBucket};
+        use crate::engines::engine_base::engine_base::SearchEngine;
+
+        const MAX_WAIT: Duration = Duration::from_secs(1);
+
+        #[test]
+        fn burst_up_to_capacity() {
+            let mut bucket = TokenBucket::new(2.5);
+
+            for _ in 0..3 {
+                assert_eq!(bucket.reserve(MAX_WAIT), Some(Duration::ZERO));
+            }
+
+            // The fourth request queues behind the burst
+            let wait = bucket.reserve(MAX_WAIT).unwrap();
+            assert!(wait > Duration::from_millis(350) && wait <= Duration::from_millis(400));
+        }
+
+        #[test]
+        fn refill_over_time() {
+            let mut bucket = TokenBucket::new(4.0);
+
+            for _ in 0..4 {
+                bucket.reserve(MAX_WAIT);
+            }
+            bucket.last_refill -= Duration::from_millis(500);
+            bucket.reserve(MAX_WAIT);
+
+            assert!((bucket.tokens - 1.0).abs() < 0.01);
+
+            // Idle time doesn't refill more than the capacity
+            bucket.last_refill = Instant::now() - Duration::from_secs(60);
+            bucket.reserve(MAX_WAIT);
+
+            assert!((bucket.tokens - 3.0).abs() < 0.01);
+        }
+
+        #[test]
+        fn too_long_waits_keep_the_token() {
+            let mut bucket = TokenBucket::new(1.0);
+
+            assert_eq!(bucket.reserve(MAX_WAIT), Some(Duration::ZERO));
+            assert_eq!(bucket.reserve(Duration::from_millis(100)), None);
+            assert!(bucket.tokens > -0.01);
+            assert!(bucket.reserve(MAX_WAIT).is_some());
+        }
+
+        #[tokio::test]
+        async fn max_wait_timeout() {
+            let limit = RateLimit {
+                requests_per_second: 2.0,
+                max_concurrency: 1,
+                max_wait_ms: 50,
+            };
+            let limiter = RateLimiter::new(limit, AHashMap::new());
+
+            let permit = limiter.acquire(&SearchEngine::Bing).await;
+            assert!(permit.is_some());
+
+            // The only slot is taken
+            let start = Instant::now();
+            assert!(limiter.acquire(&SearchEngine::Bing).await.is_none());
+            assert!(start.elapsed() >= Duration::from_millis(50));
+
+            // The search that timed out didn't take the second token
+            drop(permit);
+            assert!(limiter.acquire(&SearchEngine::Bing).await.is_some());
+
+            // The tokens are used up and refilling one takes longer than `max_wait_ms`
+            assert!(limiter.acquire(&SearchEngine::Bing).await.is_none());
+        }
+    }
+}
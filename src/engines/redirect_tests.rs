@@ -0,0 +1,101 @@
+// Tests of the resolution of result links to their destinations
+pub mod redirect_tests {
+    use crate::{
+        engines::{
+            duckduckgo::duckduckgo::DuckDuckGo,
+            engine_base::engine_base::{EngineBase, SearchResult},
+            redirects::redirects::{Redirect, RedirectKind, UrlResolver},
+        },
+        utils::utils::decode_base64,
+    };
+
+    static REDIRECTS: [Redirect; 2] = [
+        Redirect {
+            host: "duckduckgo.com",
+            path: "/l/",
+            kind: RedirectKind::QueryParameter("uddg"),
+        },
+        Redirect {
+            host: "bing.com",
+            path: "/ck/a",
+            kind: RedirectKind::BingClick,
+        },
+    ];
+
+    fn resolver() -> UrlResolver {
+        UrlResolver::new(Some("https://html.duckduckgo.com/html"), &REDIRECTS)
+    }
+
+    #[test]
+    fn relative_links() {
+        let resolver = resolver();
+
+        assert_eq!(
+            resolver.resolve("//example.com/page").as_deref(),
+            Some("https://example.com/page")
+        );
+        assert_eq!(
+            resolver.resolve("/about").as_deref(),
+            Some("https://html.duckduckgo.com/about")
+        );
+        assert_eq!(resolver.resolve("javascript:void(0)"), None);
+        assert_eq!(UrlResolver::default().resolve("/about"), None);
+    }
+
+    #[test]
+    fn query_parameter_redirects() {
+        assert_eq!(
+            resolver()
+                .resolve("//duckduckgo.com/l/?uddg=https%3A%2F%2Fexample.com%2Fa%3Fb%3D1%26c%3D2&rut=abc")
+                .as_deref(),
+            Some("https://example.com/a?b=1&c=2")
+        );
+        // Other paths of the engine are kept
+        assert_eq!(
+            resolver()
+                .resolve("https://duckduckgo.com/?q=test&uddg=https%3A%2F%2Fexample.com")
+                .as_deref(),
+            Some("https://duckduckgo.com/?q=test&uddg=https%3A%2F%2Fexample.com")
+        );
+    }
+
+    #[test]
+    fn bing_click_redirects() {
+        assert_eq!(
+            resolver()
+                .resolve("https://www.bing.com/ck/a?!&&p=1234&ptn=3&u=a1aHR0cHM6Ly9leGFtcGxlLmNvbS9wYWdlP3E9MQ&ntb=1")
+                .as_deref(),
+            Some("https://example.com/page?q=1")
+        );
+        // Undecodable redirects are kept
+        assert_eq!(
+            resolver()
+                .resolve("https://www.bing.com/ck/a?u=b1invalid")
+                .as_deref(),
+            Some("https://www.bing.com/ck/a?u=b1invalid")
+        );
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(decode_base64("aGVsbG8").as_deref(), Some(&b"hello"[..]));
+        assert_eq!(decode_base64("aGVsbG8=").as_deref(), Some(&b"hello"[..]));
+        assert_eq!(
+            decode_base64("-_-_").as_deref(),
+            Some(&[0xfb, 0xff, 0xbf][..])
+        );
+        assert_eq!(decode_base64("a b"), None);
+    }
+
+    #[test]
+    fn duckduckgo_results_link_to_destination() {
+        let response = r#"<div id="links"><div class="result web-result"><h2 class="result__title"><a rel="nofollow" class="result__a" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.speedtest.net%2F&amp;rut=0a1b">Speedtest</a></h2><a class="result__snippet" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.speedtest.net%2F">Test your speed</a></div></div>"#;
+
+        let mut parser = DuckDuckGo::new();
+        parser.push_packet(response.as_bytes());
+        let results = std::iter::from_fn(|| parser.parse_next()).collect::<Vec<SearchResult>>();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "https://www.speedtest.net/");
+    }
+}
@@ -0,0 +1,106 @@
+// Resolution of result links to the pages they lead to.
+//
+// Engines link to results relative to their own page (e.g. `//duckduckgo.com/l/...`),
+// and some route clicks through a redirect that tells them which result was opened.
+// Links are made absolute using the engine's base url, and known redirects of the
+// engine are replaced by their destination, so that results are linked directly.
+pub mod redirects {
+    use reqwest::Url;
+
+    use crate::utils::utils::decode_base64;
+
+    // Redirects to redirects are followed up to this depth
+    const MAX_REDIRECT_DEPTH: usize = 4;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum RedirectKind {
+        /// The destination is a query parameter, e.g. `uddg` of DuckDuckGo's `/l/`
+        QueryParameter(&'static str),
+        /// The destination is the `u` parameter, base64 encoded and prefixed by `a1`
+        BingClick,
+    }
+
+    /// Links to `path` on `host` or one of its subdomains are redirects
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Redirect {
+        pub host: &'static str,
+        pub path: &'static str,
+        pub kind: RedirectKind,
+    }
+
+    /// Turns the result links of an engine into absolute links to their destinations
+    #[derive(Clone, Debug)]
+    pub struct UrlResolver {
+        base: Option<Url>,
+        redirects: &'static [Redirect],
+    }
+
+    impl Default for UrlResolver {
+        fn default() -> Self {
+            Self::new(None, &[])
+        }
+    }
+
+    impl UrlResolver {
+        /// `base` is the url of the engine's result page, which links are relative to
+        pub fn new(base: Option<&str>, redirects: &'static [Redirect]) -> Self {
+            Self {
+                base: base.and_then(|base| Url::parse(base).ok()),
+                redirects,
+            }
+        }
+
+        /// Absolute url of the page `link` leads to, or `None` if it doesn't lead to a
+        /// web page, e.g. `javascript:` links
+        pub fn resolve(&self, link: &str) -> Option<String> {
+            let link = link.trim();
+            let mut url = match &self.base {
+                Some(base) => base.join(link).ok()?,
+                None => Url::parse(link).ok()?,
+            };
+
+            for _ in 0..MAX_REDIRECT_DEPTH {
+                let Some(destination) = self.destination(&url) else {
+                    break;
+                };
+
+                url = destination;
+            }
+
+            match url.scheme() {
+                "http" | "https" => Some(url.to_string()),
+                _ => None,
+            }
+        }
+
+        fn destination(&self, url: &Url) -> Option<Url> {
+            let host = url.host_str()?;
+            let redirect = self.redirects.iter().find(|redirect| {
+                let is_host = host == redirect.host
+                    || host
+                        .strip_suffix(redirect.host)
+                        .is_some_and(|subdomain| subdomain.ends_with('.'));
+
+                is_host && url.path().starts_with(redirect.path)
+            })?;
+
+            let parameter = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+
+            let destination = match redirect.kind {
+                RedirectKind::QueryParameter(name) => parameter(name)?,
+                RedirectKind::BingClick => {
+                    let encoded = parameter("u")?;
+
+                    String::from_utf8(decode_base64(encoded.strip_prefix("a1")?)?).ok()?
+                }
+            };
+
+            // Destinations may be relative to the redirect, e.g. Bing's own pages
+            url.join(&destination).ok()
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Registry of all search engines that are queried for a search
+pub mod registry {
+    use std::{sync::Arc, time::Duration};
+
+    use ahash::AHashMap;
+    use async_trait::async_trait;
+    use reqwest::{Client, RequestBuilder};
+    use tokio::{
+        sync::mpsc::Sender,
+        time::{self, Instant},
+    };
+
+    use crate::{
+        engines::{
+            bing::bing::Bing,
+            brave::brave::Brave,
+            cooldown::cooldown::EngineCooldowns,
+            drift::drift::ParserDriftMonitor,
+            duckduckgo::duckduckgo::DuckDuckGo,
+            engine_base::engine_base::{EngineBase, EngineError, SearchEngine, SearchEvent},
+            rate_limit::rate_limit::RateLimiter,
+        },
+        helpers::helpers::build_default_client,
+        ranking::ranking::RankFusion,
+        recording::recording::ResponseTransport,
+    };
+
+    /// A search engine that can be registered in the `EngineRegistry`.
+    ///
+    /// This trait is object safe, so engines can be stored as `Arc<dyn Engine>`.
+    #[async_trait]
+    pub trait Engine: Send + Sync {
+        fn kind(&self) -> SearchEngine;
+
+        /// Create a fresh parser for a single response
+        fn parser(&self) -> Box<dyn EngineBase>;
+
+        fn build_request(&self, client: &Client, query: &str) -> RequestBuilder;
+
+        /// Search for `query` and return the number of results sent to `tx`
+        async fn search(
+            &self,
+            query: &str,
+            transport: &ResponseTransport,
+            drift: &ParserDriftMonitor,
+            tx: Sender<SearchEvent>,
+        ) -> Result<usize, EngineError> {
+            let client = build_default_client();
+            let kind = self.kind();
+            let request =
+                transport.request(&client, &kind, query, self.build_request(&client, query))?;
+
+            self.parser()
+                .handle_request(
+                    kind,
+                    request,
+                    transport.recording(&kind, query),
+                    Some(drift),
+                    tx,
+                )
+                .await
+        }
+    }
+
+    /// Services of the registry that every engine search uses
+    #[derive(Clone)]
+    pub struct SearchContext {
+        pub cooldowns: Arc<EngineCooldowns>,
+        pub rate_limiter: Arc<RateLimiter>,
+        pub transport: Arc<ResponseTransport>,
+        pub drift: Arc<ParserDriftMonitor>,
+    }
+
+    /// Run a search on `engine` and report its whole lifecycle as `SearchEvent`s.
+    ///
+    /// The search is aborted once `timeout` is reached.
+    pub async fn run_search(
+        engine: Arc<dyn Engine>,
+        query: String,
+        timeout: Duration,
+        context: SearchContext,
+        tx: Sender<SearchEvent>,
+    ) {
+        let kind = engine.kind();
+
+        // Held until the search is done, so it counts towards the engine's concurrency
+        let _permit = match context.rate_limiter.acquire(&kind).await {
+            Some(permit) => permit,
+            None => {
+                let _ = tx
+                    .send(SearchEvent::Failed {
+                        engine: kind,
+                        error: EngineError::RateLimited,
+                    })
+                    .await;
+
+                return;
+            }
+        };
+
+        let start = Instant::now();
+
+        if tx
+            .send(SearchEvent::Started { engine: kind })
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        // Dropping the search future on timeout aborts the upstream request
+        let event = match time::timeout(
+            timeout,
+            engine.search(&query, &context.transport, &context.drift, tx.clone()),
+        )
+        .await
+        {
+            Ok(Ok(count)) => SearchEvent::Finished {
+                engine: kind,
+                duration: start.elapsed(),
+                count,
+            },
+            Ok(Err(error)) => SearchEvent::Failed {
+                engine: kind,
+                error,
+            },
+            Err(_) => SearchEvent::Failed {
+                engine: kind,
+                error: EngineError::Timeout,
+            },
+        };
+
+        match &event {
+            SearchEvent::Finished { .. } => context.cooldowns.report_success(&kind),
+            SearchEvent::Failed {
+                error: EngineError::Blocked,
+                ..
+            } => {
+                let cooldown = context.cooldowns.report_blocked(kind);
+
+                log::warn!("{} is blocked, skipping it for {:?}", kind, cooldown);
+            }
+            _ => {}
+        }
+
+        let _ = tx.send(event).await;
+    }
+
+    const DEFAULT_ENGINE_TIMEOUT: Duration = Duration::from_secs(3);
+    const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[derive(Clone)]
+    pub struct EngineRegistry {
+        engines: Vec<Arc<dyn Engine>>,
+        engine_timeouts: AHashMap<SearchEngine, Duration>,
+        pub default_engine_timeout: Duration,
+        /// Time after which the page is closed with whatever results arrived
+        pub query_timeout: Duration,
+        pub cooldowns: Arc<EngineCooldowns>,
+        pub rate_limiter: Arc<RateLimiter>,
+        pub transport: Arc<ResponseTransport>,
+        pub drift: Arc<ParserDriftMonitor>,
+        /// Orders the results of all engines
+        pub ranking: Arc<RankFusion>,
+    }
+
+    impl Default for EngineRegistry {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl EngineRegistry {
+        pub fn new() -> Self {
+            Self {
+                engines: Vec::new(),
+                engine_timeouts: AHashMap::new(),
+                default_engine_timeout: DEFAULT_ENGINE_TIMEOUT,
+                query_timeout: DEFAULT_QUERY_TIMEOUT,
+                cooldowns: Arc::new(EngineCooldowns::new()),
+                rate_limiter: Arc::new(RateLimiter::default()),
+                transport: Arc::new(ResponseTransport::Live),
+                drift: Arc::new(ParserDriftMonitor::default()),
+                ranking: Arc::new(RankFusion::default()),
+            }
+        }
+
+        /// Registry containing all engines shipped with tifsep
+        pub fn with_default_engines() -> Self {
+            let mut registry = Self::new();
+
+            registry.register(Arc::new(Brave::new()));
+            registry.register(Arc::new(Bing::new()));
+            registry.register(Arc::new(DuckDuckGo::new()));
+
+            registry
+        }
+
+        /// Register an engine; an already registered engine of the same kind is replaced
+        pub fn register(&mut self, engine: Arc<dyn Engine>) {
+            match self
+                .engines
+                .iter()
+                .position(|registered| registered.kind() == engine.kind())
+            {
+                Some(index) => self.engines[index] = engine,
+                None => self.engines.push(engine),
+            }
+        }
+
+        pub fn set_engine_timeout(&mut self, engine: SearchEngine, timeout: Duration) {
+            self.engine_timeouts.insert(engine, timeout);
+        }
+
+        pub fn engine_timeout(&self, engine: &SearchEngine) -> Duration {
+            self.engine_timeouts
+                .get(engine)
+                .copied()
+                .unwrap_or(self.default_engine_timeout)
+        }
+
+        pub fn search_context(&self) -> SearchContext {
+            SearchContext {
+                cooldowns: self.cooldowns.clone(),
+                rate_limiter: self.rate_limiter.clone(),
+                transport: self.transport.clone(),
+                drift: self.drift.clone(),
+            }
+        }
+
+        pub fn kinds(&self) -> Vec<SearchEngine> {
+            self.engines.iter().map(|engine| engine.kind()).collect()
+        }
+
+        pub fn engines(&self) -> &[Arc<dyn Engine>] {
+            &self.engines
+        }
+    }
+}
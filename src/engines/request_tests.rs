@@ -0,0 +1,51 @@
+// Tests of the requests the built-in engines send
+pub mod request_tests {
+    use reqwest::Url;
+
+    use crate::{
+        engines::{
+            bing::bing::Bing, brave::brave::Brave, duckduckgo::duckduckgo::DuckDuckGo,
+            registry::registry::Engine,
+        },
+        helpers::helpers::build_default_client,
+    };
+
+    const QUERY: &str = "rust & c++ #1 100% ?q=x";
+
+    /// Value of the `q` parameter in the query of `url`
+    fn query_parameter(url: &Url) -> Option<String> {
+        url.query_pairs()
+            .find(|(name, _)| name == "q")
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[test]
+    fn queries_are_encoded_in_the_url() {
+        let client = build_default_client();
+        let engines: [Box<dyn Engine>; 2] = [Box::new(Brave::new()), Box::new(Bing::new())];
+
+        for engine in engines {
+            let request = engine.build_request(&client, QUERY).build().unwrap();
+
+            assert_eq!(
+                query_parameter(request.url()).as_deref(),
+                Some(QUERY),
+                "{}",
+                engine.kind()
+            );
+            assert_eq!(request.url().query_pairs().count(), 1, "{}", engine.kind());
+        }
+    }
+
+    #[test]
+    fn queries_are_encoded_in_the_form() {
+        let request = DuckDuckGo::new()
+            .build_request(&build_default_client(), QUERY)
+            .build()
+            .unwrap();
+        let body = request.body().and_then(|body| body.as_bytes()).unwrap();
+        let form = Url::parse(&format!("http://form/?{}", String::from_utf8_lossy(body))).unwrap();
+
+        assert_eq!(query_parameter(&form).as_deref(), Some(QUERY));
+    }
+}
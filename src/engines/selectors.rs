@@ -0,0 +1,1093 @@
+// Extraction of search results using CSS-style selectors instead of regexes.
+// The response is tokenized while it arrives, and every result is returned as soon as
+// its element is closed, so results are still emitted packet by packet.
+//
+// Supported selectors are compound selectors such as `div.result#id[attr="value"]`,
+// combined with descendant (` `) and child (`>`) combinators.
+// Compound selectors may use `:nth-child(n)`, `:nth-of-type(n)`, `:first-child`
+// and `:first-of-type`.
+//
+// Fields are extracted from the text of the first matching element inside a result,
+// or from one of its attributes by appending `@attribute`, e.g. `a.title@href`.
+// A field that is only `@attribute` is read from the result element itself.
+// Sitelinks are the opposite: every element matching their selector is a sitelink,
+// whose title is its text and whose url is its `href`.
+pub mod selectors {
+    use std::{collections::VecDeque, error::Error, sync::Arc};
+
+    use ahash::AHashMap;
+    use regex::Regex;
+    use serde::Deserialize;
+
+    use crate::{
+        engines::{
+            dates::dates::DateParser,
+            engine_base::engine_base::{EngineBase, SearchEngine, SearchResult},
+            extras::extras::build_extras,
+            html_tokenizer::html_tokenizer::{HtmlTokenizer, Token},
+            redirects::redirects::UrlResolver,
+        },
+        utils::utils::{decode_html_attribute, decode_html_entities, decode_utf8_packet},
+    };
+
+    /// Elements that never have content or an end tag
+    const VOID_ELEMENTS: [&str; 14] = [
+        "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param",
+        "source", "track", "wbr",
+    ];
+
+    /// Elements that are implicitly closed by a following sibling of the same kind
+    const SELF_NESTING_ELEMENTS: [&str; 8] = ["li", "p", "option", "tr", "td", "th", "dt", "dd"];
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum AttributeOperator {
+        Exists,
+        Equals(String),
+        StartsWith(String),
+        EndsWith(String),
+        Contains(String),
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct AttributeSelector {
+        name: String,
+        operator: AttributeOperator,
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct CompoundSelector {
+        tag: Option<String>,
+        id: Option<String>,
+        classes: Vec<String>,
+        attributes: Vec<AttributeSelector>,
+        nth_child: Option<usize>,
+        nth_of_type: Option<usize>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Combinator {
+        Descendant,
+        Child,
+    }
+
+    /// An open element of the document
+    #[derive(Clone, Debug)]
+    struct Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        // 1-based position among all element siblings and among siblings with the same name
+        child_index: usize,
+        type_index: usize,
+    }
+
+    impl Element {
+        fn attribute(&self, name: &str) -> Option<&str> {
+            self.attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    impl CompoundSelector {
+        fn matches(&self, element: &Element) -> bool {
+            if self.tag.as_ref().is_some_and(|tag| *tag != element.name) {
+                return false;
+            }
+
+            if self
+                .id
+                .as_ref()
+                .is_some_and(|id| element.attribute("id") != Some(id))
+            {
+                return false;
+            }
+
+            if !self.classes.is_empty() {
+                let classes = element.attribute("class").unwrap_or_default();
+
+                if !self
+                    .classes
+                    .iter()
+                    .all(|class| classes.split_ascii_whitespace().any(|other| other == class))
+                {
+                    return false;
+                }
+            }
+
+            if self
+                .nth_child
+                .is_some_and(|index| index != element.child_index)
+                || self
+                    .nth_of_type
+                    .is_some_and(|index| index != element.type_index)
+            {
+                return false;
+            }
+
+            self.attributes.iter().all(|selector| {
+                let Some(value) = element.attribute(&selector.name) else {
+                    return false;
+                };
+
+                match &selector.operator {
+                    AttributeOperator::Exists => true,
+                    AttributeOperator::Equals(expected) => value == expected,
+                    AttributeOperator::StartsWith(prefix) => value.starts_with(prefix.as_str()),
+                    AttributeOperator::EndsWith(suffix) => value.ends_with(suffix.as_str()),
+                    AttributeOperator::Contains(part) => value.contains(part.as_str()),
+                }
+            })
+        }
+    }
+
+    /// A parsed CSS-style selector, e.g. `#links div.result > h2 a`
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Selector {
+        // The combinator of a part describes its relation to the previous part;
+        // it's ignored for the first part
+        parts: Vec<(Combinator, CompoundSelector)>,
+    }
+
+    impl Selector {
+        pub fn parse(selector: &str) -> Result<Self, Box<dyn Error>> {
+            let mut parser = SelectorParser {
+                selector,
+                position: 0,
+            };
+            let mut parts = Vec::new();
+            let mut combinator = Combinator::Descendant;
+
+            loop {
+                let had_whitespace = parser.skip_whitespace();
+
+                match parser.peek() {
+                    None => break,
+                    Some('>') => {
+                        if parts.is_empty() {
+                            return Err(parser.error("selector can't start with `>`"));
+                        }
+
+                        parser.position += 1;
+                        combinator = Combinator::Child;
+
+                        continue;
+                    }
+                    Some(_) => {
+                        if !had_whitespace && !parts.is_empty() && combinator != Combinator::Child {
+                            return Err(parser.error("unexpected character"));
+                        }
+
+                        parts.push((combinator, parser.parse_compound()?));
+                        combinator = Combinator::Descendant;
+                    }
+                }
+            }
+
+            if parts.is_empty() || combinator == Combinator::Child {
+                return Err(parser.error("incomplete selector"));
+            }
+
+            Ok(Self { parts })
+        }
+
+        /// Whether the last element of `stack` matches, only looking at ancestors
+        /// from `scope` onwards
+        fn matches(&self, stack: &[Element], scope: usize) -> bool {
+            match stack.len().checked_sub(1) {
+                Some(index) if index >= scope => {
+                    self.matches_part(self.parts.len() - 1, stack, index, scope)
+                }
+                _ => false,
+            }
+        }
+
+        fn matches_part(&self, part: usize, stack: &[Element], index: usize, scope: usize) -> bool {
+            let (combinator, compound) = &self.parts[part];
+
+            if !compound.matches(&stack[index]) {
+                return false;
+            }
+
+            if part == 0 {
+                return true;
+            }
+
+            match combinator {
+                Combinator::Child => {
+                    index > scope && self.matches_part(part - 1, stack, index - 1, scope)
+                }
+                Combinator::Descendant => (scope..index)
+                    .any(|ancestor| self.matches_part(part - 1, stack, ancestor, scope)),
+            }
+        }
+    }
+
+    struct SelectorParser<'a> {
+        selector: &'a str,
+        position: usize,
+    }
+
+    impl<'a> SelectorParser<'a> {
+        fn error(&self, message: &str) -> Box<dyn Error> {
+            format!(
+                "Invalid selector `{}` at position {}: {}",
+                self.selector, self.position, message
+            )
+            .into()
+        }
+
+        fn peek(&self) -> Option<char> {
+            self.selector[self.position..].chars().next()
+        }
+
+        fn skip_whitespace(&mut self) -> bool {
+            let rest = &self.selector[self.position..];
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+
+            rest.len() != trimmed.len()
+        }
+
+        fn read_identifier(&mut self) -> Result<String, Box<dyn Error>> {
+            let rest = &self.selector[self.position..];
+            let length = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or(rest.len());
+
+            if length == 0 {
+                return Err(self.error("expected a name"));
+            }
+
+            self.position += length;
+
+            Ok(rest[..length].to_string())
+        }
+
+        fn expect(&mut self, expected: char) -> Result<(), Box<dyn Error>> {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("expected `{}`", expected)));
+            }
+
+            self.position += expected.len_utf8();
+
+            Ok(())
+        }
+
+        fn parse_compound(&mut self) -> Result<CompoundSelector, Box<dyn Error>> {
+            let mut compound = CompoundSelector::default();
+            let mut is_universal = false;
+
+            match self.peek() {
+                Some('*') => {
+                    self.position += 1;
+                    is_universal = true;
+                }
+                Some(c) if c.is_alphabetic() => {
+                    compound.tag = Some(self.read_identifier()?.to_ascii_lowercase());
+                }
+                _ => {}
+            }
+
+            loop {
+                match self.peek() {
+                    Some('.') => {
+                        self.position += 1;
+                        compound.classes.push(self.read_identifier()?);
+                    }
+                    Some('#') => {
+                        self.position += 1;
+                        compound.id = Some(self.read_identifier()?);
+                    }
+                    Some('[') => {
+                        self.position += 1;
+                        compound.attributes.push(self.parse_attribute()?);
+                    }
+                    Some(':') => {
+                        self.position += 1;
+                        self.parse_pseudo_class(&mut compound)?;
+                    }
+                    _ => break,
+                }
+            }
+
+            if !is_universal && compound == CompoundSelector::default() {
+                return Err(self.error("expected a selector"));
+            }
+
+            Ok(compound)
+        }
+
+        fn parse_attribute(&mut self) -> Result<AttributeSelector, Box<dyn Error>> {
+            self.skip_whitespace();
+            let name = self.read_identifier()?.to_ascii_lowercase();
+            self.skip_whitespace();
+
+            let operator = match self.peek() {
+                Some(']') => {
+                    self.position += 1;
+
+                    return Ok(AttributeSelector {
+                        name,
+                        operator: AttributeOperator::Exists,
+                    });
+                }
+                Some(operator @ ('^' | '$' | '*')) => {
+                    self.position += 1;
+                    self.expect('=')?;
+
+                    operator
+                }
+                _ => {
+                    self.expect('=')?;
+
+                    '='
+                }
+            };
+
+            self.skip_whitespace();
+            let value = self.read_attribute_value()?;
+            self.skip_whitespace();
+            self.expect(']')?;
+
+            Ok(AttributeSelector {
+                name,
+                operator: match operator {
+                    '^' => AttributeOperator::StartsWith(value),
+                    '$' => AttributeOperator::EndsWith(value),
+                    '*' => AttributeOperator::Contains(value),
+                    _ => AttributeOperator::Equals(value),
+                },
+            })
+        }
+
+        fn read_attribute_value(&mut self) -> Result<String, Box<dyn Error>> {
+            match self.peek() {
+                Some(quote @ ('"' | '\'')) => {
+                    self.position += 1;
+                    let rest = &self.selector[self.position..];
+                    let Some(length) = rest.find(quote) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.position += length + 1;
+
+                    Ok(rest[..length].to_string())
+                }
+                _ => self.read_identifier(),
+            }
+        }
+
+        fn parse_pseudo_class(
+            &mut self,
+            compound: &mut CompoundSelector,
+        ) -> Result<(), Box<dyn Error>> {
+            let name = self.read_identifier()?.to_ascii_lowercase();
+
+            match name.as_str() {
+                "first-child" => compound.nth_child = Some(1),
+                "first-of-type" => compound.nth_of_type = Some(1),
+                "nth-child" | "nth-of-type" => {
+                    self.expect('(')?;
+                    self.skip_whitespace();
+                    let index = self
+                        .read_identifier()?
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|index| *index > 0)
+                        .ok_or_else(|| self.error("expected a positive number"))?;
+                    self.skip_whitespace();
+                    self.expect(')')?;
+
+                    if name == "nth-child" {
+                        compound.nth_child = Some(index);
+                    } else {
+                        compound.nth_of_type = Some(index);
+                    }
+                }
+                _ => return Err(self.error(&format!("unsupported pseudo-class `{}`", name))),
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Where a field of a result is read from, e.g. `a.title@href`
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct FieldSelector {
+        // `None` if the field is read from the result element itself
+        selector: Option<Selector>,
+        // The text content is used if no attribute is given
+        attribute: Option<String>,
+    }
+
+    impl FieldSelector {
+        pub fn parse(field: &str) -> Result<Self, Box<dyn Error>> {
+            let (selector, attribute) = match field.rsplit_once('@') {
+                Some((selector, attribute))
+                    if !attribute.is_empty()
+                        && attribute
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == ':') =>
+                {
+                    (selector, Some(attribute.to_ascii_lowercase()))
+                }
+                _ => (field, None),
+            };
+
+            let selector = match selector.trim() {
+                "" if attribute.is_some() => None,
+                selector => Some(Selector::parse(selector)?),
+            };
+
+            Ok(Self {
+                selector,
+                attribute,
+            })
+        }
+    }
+
+    /// Selectors of the result element and of the fields inside it
+    #[derive(Clone, Debug)]
+    pub struct ResultSelectors {
+        pub result: Selector,
+        pub title: FieldSelector,
+        pub url: FieldSelector,
+        pub description: FieldSelector,
+        pub image: Option<FieldSelector>,
+        pub date: Option<FieldSelector>,
+        pub date_parser: DateParser,
+        pub url_resolver: UrlResolver,
+        pub breadcrumbs: Option<FieldSelector>,
+        pub sitelinks: Option<Selector>,
+        /// Text that ratings, durations and reply counts are read from
+        pub attributes: Option<FieldSelector>,
+    }
+
+    /// Selector map of an engine, as written in the config
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct SelectorMap {
+        pub result: String,
+        pub title: String,
+        pub url: String,
+        pub description: String,
+        pub image: Option<String>,
+        pub date: Option<String>,
+        pub breadcrumbs: Option<String>,
+        pub sitelinks: Option<String>,
+        pub attributes: Option<String>,
+    }
+
+    impl ResultSelectors {
+        pub fn from_map(
+            map: &SelectorMap,
+            date_parser: DateParser,
+            url_resolver: UrlResolver,
+        ) -> Result<Self, Box<dyn Error>> {
+            let optional =
+                |field: &Option<String>| field.as_deref().map(FieldSelector::parse).transpose();
+
+            Ok(Self {
+                result: Selector::parse(&map.result)?,
+                title: FieldSelector::parse(&map.title)?,
+                url: FieldSelector::parse(&map.url)?,
+                description: FieldSelector::parse(&map.description)?,
+                image: optional(&map.image)?,
+                date: optional(&map.date)?,
+                date_parser,
+                url_resolver,
+                breadcrumbs: optional(&map.breadcrumbs)?,
+                sitelinks: map.sitelinks.as_deref().map(Selector::parse).transpose()?,
+                attributes: optional(&map.attributes)?,
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    enum FieldValue {
+        #[default]
+        Missing,
+        // Text of the element at the given stack index is being collected
+        Collecting(usize, String),
+        Found(String),
+    }
+
+    /// Something for every field of a result, e.g. its selector or its value
+    #[derive(Clone, Debug, Default)]
+    struct Fields<T> {
+        title: T,
+        url: T,
+        description: T,
+        image: T,
+        date: T,
+        breadcrumbs: T,
+        attributes: T,
+    }
+
+    impl<T> Fields<T> {
+        fn map<U>(self, mut f: impl FnMut(T) -> U) -> Fields<U> {
+            Fields {
+                title: f(self.title),
+                url: f(self.url),
+                description: f(self.description),
+                image: f(self.image),
+                date: f(self.date),
+                breadcrumbs: f(self.breadcrumbs),
+                attributes: f(self.attributes),
+            }
+        }
+
+        fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+            [
+                &mut self.title,
+                &mut self.url,
+                &mut self.description,
+                &mut self.image,
+                &mut self.date,
+                &mut self.breadcrumbs,
+                &mut self.attributes,
+            ]
+            .into_iter()
+        }
+
+        fn into_values(self) -> impl Iterator<Item = T> {
+            [
+                self.title,
+                self.url,
+                self.description,
+                self.image,
+                self.date,
+                self.breadcrumbs,
+                self.attributes,
+            ]
+            .into_iter()
+        }
+    }
+
+    /// A result whose element is still open
+    #[derive(Clone, Debug)]
+    struct OpenResult {
+        // Stack index of the result element
+        index: usize,
+        fields: Fields<FieldValue>,
+        // Url and title of every sitelink
+        sitelinks: Vec<(String, FieldValue)>,
+    }
+
+    /// Streaming parser that extracts results using `ResultSelectors`
+    #[derive(Clone, Debug)]
+    pub struct SelectorResultParser {
+        engine: SearchEngine,
+        selectors: Arc<ResultSelectors>,
+        block_page: Option<Arc<Regex>>,
+        tokenizer: HtmlTokenizer,
+        pending_bytes: Vec<u8>,
+        stack: Vec<Element>,
+        // Number of element children and children per name of every open element,
+        // plus one entry for the document itself
+        sibling_counts: Vec<(usize, AHashMap<String, usize>)>,
+        result: Option<OpenResult>,
+        // Results whose element has been closed but that haven't been returned yet
+        ready: VecDeque<SearchResult>,
+    }
+
+    impl SelectorResultParser {
+        pub fn new(
+            engine: SearchEngine,
+            selectors: Arc<ResultSelectors>,
+            block_page: Option<Arc<Regex>>,
+        ) -> Self {
+            Self {
+                engine,
+                selectors,
+                block_page,
+                tokenizer: HtmlTokenizer::new(),
+                pending_bytes: Vec::new(),
+                stack: Vec::new(),
+                sibling_counts: vec![(0, AHashMap::new())],
+                result: None,
+                ready: VecDeque::new(),
+            }
+        }
+
+        fn field_selectors(&self) -> Fields<Option<&FieldSelector>> {
+            Fields {
+                title: Some(&self.selectors.title),
+                url: Some(&self.selectors.url),
+                description: Some(&self.selectors.description),
+                image: self.selectors.image.as_ref(),
+                date: self.selectors.date.as_ref(),
+                breadcrumbs: self.selectors.breadcrumbs.as_ref(),
+                attributes: self.selectors.attributes.as_ref(),
+            }
+        }
+
+        fn handle_start_tag(
+            &mut self,
+            name: String,
+            attributes: Vec<(String, String)>,
+            self_closing: bool,
+        ) {
+            if SELF_NESTING_ELEMENTS.contains(&name.as_str())
+                && self
+                    .stack
+                    .last()
+                    .is_some_and(|element| element.name == name)
+            {
+                self.close_top();
+            }
+
+            let (children, types) = self
+                .sibling_counts
+                .last_mut()
+                .expect("the document entry is never removed");
+            *children += 1;
+            let type_index = types.entry(name.clone()).or_insert(0);
+            *type_index += 1;
+
+            self.stack.push(Element {
+                child_index: *children,
+                type_index: *type_index,
+                name,
+                attributes,
+            });
+            self.sibling_counts.push((0, AHashMap::new()));
+
+            let index = self.stack.len() - 1;
+
+            // Keep the values of separate elements apart, e.g. "4.5/5" and "(120)"
+            if let Some(OpenResult { fields, .. }) = &mut self.result {
+                if let FieldValue::Collecting(_, collected) = &mut fields.attributes {
+                    collected.push(' ');
+                }
+            }
+
+            match &self.result {
+                None => {
+                    if self.selectors.result.matches(&self.stack, 0) {
+                        self.result = Some(OpenResult {
+                            index,
+                            fields: Fields::default(),
+                            sitelinks: Vec::new(),
+                        });
+
+                        self.match_fields(index, true);
+                    }
+                }
+                Some(_) => self.match_fields(index, false),
+            }
+
+            let is_void = VOID_ELEMENTS.contains(&self.stack[index].name.as_str());
+
+            if is_void || self_closing {
+                self.close_top();
+            }
+        }
+
+        /// Start collecting the fields whose selector matches the element at `index`
+        fn match_fields(&mut self, index: usize, is_result_element: bool) {
+            let Some(result) = &self.result else {
+                return;
+            };
+            let scope = result.index + 1;
+
+            let matches = self.field_selectors().map(|field| {
+                let field = field?;
+
+                let is_match = match &field.selector {
+                    None => is_result_element,
+                    Some(selector) => !is_result_element && selector.matches(&self.stack, scope),
+                };
+
+                if !is_match {
+                    return None;
+                }
+
+                Some(match &field.attribute {
+                    Some(attribute) => match self.stack[index].attribute(attribute) {
+                        Some(value) => FieldValue::Found(value.to_string()),
+                        None => FieldValue::Missing,
+                    },
+                    None => FieldValue::Collecting(index, String::new()),
+                })
+            });
+
+            let sitelink = match &self.selectors.sitelinks {
+                Some(selector) if !is_result_element && selector.matches(&self.stack, scope) => {
+                    self.stack[index].attribute("href").map(str::to_string)
+                }
+                _ => None,
+            };
+
+            let Some(result) = &mut self.result else {
+                return;
+            };
+
+            for (value, new_value) in result.fields.iter_mut().zip(matches.into_values()) {
+                if let (FieldValue::Missing, Some(new_value)) = (&value, new_value) {
+                    *value = new_value;
+                }
+            }
+
+            if let Some(url) = sitelink {
+                result
+                    .sitelinks
+                    .push((url, FieldValue::Collecting(index, String::new())));
+            }
+        }
+
+        fn handle_text(&mut self, text: &str) {
+            if let Some(result) = &mut self.result {
+                let sitelinks = result.sitelinks.iter_mut().map(|(_, title)| title);
+
+                for field in result.fields.iter_mut().chain(sitelinks) {
+                    if let FieldValue::Collecting(_, collected) = field {
+                        collected.push_str(text);
+                    }
+                }
+            }
+        }
+
+        /// Close the element on top of the stack, finishing its result if it's the element of one
+        fn close_top(&mut self) {
+            if self.stack.pop().is_none() {
+                return;
+            }
+            self.sibling_counts.pop();
+
+            let index = self.stack.len();
+            let Some(result) = &mut self.result else {
+                return;
+            };
+
+            let sitelinks = result.sitelinks.iter_mut().map(|(_, title)| title);
+
+            for field in result.fields.iter_mut().chain(sitelinks) {
+                if let FieldValue::Collecting(field_index, collected) = field {
+                    if *field_index == index {
+                        *field = FieldValue::Found(std::mem::take(collected));
+                    }
+                }
+            }
+
+            if result.index != index {
+                return;
+            }
+
+            if let Some(result) = self
+                .result
+                .take()
+                .and_then(|result| self.build_result(result))
+            {
+                self.ready.push_back(result);
+            }
+        }
+
+        fn handle_end_tag(&mut self, name: &str) {
+            // End tags without an open element are ignored, and elements that
+            // weren't closed explicitly are closed together with their parent
+            let Some(position) = self.stack.iter().rposition(|element| element.name == name) else {
+                return;
+            };
+
+            while self.stack.len() > position {
+                self.close_top();
+            }
+        }
+
+        fn build_result(&self, result: OpenResult) -> Option<SearchResult> {
+            let found = |field: FieldValue| match field {
+                FieldValue::Found(value) => Some(value),
+                _ => None,
+            };
+            let Fields {
+                title,
+                url,
+                description,
+                image,
+                date,
+                breadcrumbs,
+                attributes,
+            } = result.fields.map(found);
+
+            let title = clean_text(&title?);
+            let url = self
+                .selectors
+                .url_resolver
+                .resolve(&decode_html_attribute(&url?))?;
+
+            if title.is_empty() {
+                return None;
+            }
+
+            let sitelinks = result
+                .sitelinks
+                .into_iter()
+                .filter_map(|(link, title)| {
+                    Some((
+                        clean_text(&found(title)?),
+                        self.selectors
+                            .url_resolver
+                            .resolve(&decode_html_attribute(&link))?,
+                    ))
+                })
+                .collect();
+            let extras = build_extras(
+                &url,
+                breadcrumbs
+                    .map(|breadcrumbs| clean_text(&breadcrumbs))
+                    .as_deref(),
+                sitelinks,
+                attributes
+                    .map(|attributes| clean_text(&attributes))
+                    .as_deref(),
+            );
+
+            Some(SearchResult {
+                title,
+                url,
+                description: description
+                    .map(|description| clean_text(&description))
+                    .unwrap_or_default(),
+                engine: self.engine,
+                image_url: image.map(|image| decode_html_attribute(&image)),
+                date: date.and_then(|date| self.selectors.date_parser.parse(&clean_text(&date))),
+                extras,
+                position: 0,
+            })
+        }
+    }
+
+    /// Decode entities and collapse whitespace of an element's text
+    fn clean_text(text: &str) -> String {
+        decode_html_entities(&text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .trim()
+            .to_string()
+    }
+
+    impl EngineBase for SelectorResultParser {
+        fn parse_next(&mut self) -> Option<SearchResult> {
+            while self.ready.is_empty() {
+                match self.tokenizer.next_token()? {
+                    Token::StartTag {
+                        name,
+                        attributes,
+                        self_closing,
+                    } => self.handle_start_tag(name, attributes, self_closing),
+                    Token::EndTag { name } => self.handle_end_tag(&name),
+                    Token::Text(text) => self.handle_text(&text),
+                }
+            }
+
+            self.ready.pop_front()
+        }
+
+        fn push_packet(&mut self, packet: &[u8]) {
+            decode_utf8_packet(&mut self.pending_bytes, packet, |text| {
+                self.tokenizer.feed(text)
+            });
+        }
+
+        fn block_page_regex(&self) -> Option<&Regex> {
+            self.block_page.as_deref()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn compound(tag: &str) -> CompoundSelector {
+            CompoundSelector {
+                tag: Some(tag.to_string()),
+                ..CompoundSelector::default()
+            }
+        }
+
+        /// Ids of the elements matching `selector`, whose id is used as their title
+        fn matching_ids(selector: &str, html: &str) -> Vec<String> {
+            let selectors = ResultSelectors::from_map(
+                &SelectorMap {
+                    result: selector.to_string(),
+                    title: "@id".to_string(),
+                    url: "@id".to_string(),
+                    description: "p".to_string(),
+                    image: None,
+                    date: None,
+                    breadcrumbs: None,
+                    sitelinks: None,
+                    attributes: None,
+                },
+                DateParser::default(),
+                UrlResolver::new(Some("https://example.com/"), &[]),
+            )
+            .unwrap();
+            let mut parser =
+                SelectorResultParser::new(SearchEngine::Bing, Arc::new(selectors), None);
+            parser.push_packet(html.as_bytes());
+
+            std::iter::from_fn(|| parser.parse_next())
+                .map(|result| result.title)
+                .collect()
+        }
+
+        #[test]
+        fn parses_combinators() {
+            let selector = Selector::parse("div.results >  ul li#first").unwrap();
+
+            assert_eq!(
+                selector.parts,
+                vec![
+                    (
+                        Combinator::Descendant,
+                        CompoundSelector {
+                            classes: vec!["results".to_string()],
+                            ..compound("div")
+                        }
+                    ),
+                    (Combinator::Child, compound("ul")),
+                    (
+                        Combinator::Descendant,
+                        CompoundSelector {
+                            id: Some("first".to_string()),
+                            ..compound("li")
+                        }
+                    ),
+                ]
+            );
+            assert_eq!(
+                Selector::parse("div>ul").unwrap().parts[1].0,
+                Combinator::Child
+            );
+        }
+
+        #[test]
+        fn parses_pseudo_classes() {
+            let selector = Selector::parse("li:nth-child( 3 ):first-of-type").unwrap();
+
+            assert_eq!(
+                selector.parts[0].1,
+                CompoundSelector {
+                    nth_child: Some(3),
+                    nth_of_type: Some(1),
+                    ..compound("li")
+                }
+            );
+            assert_eq!(
+                Selector::parse("*:first-child").unwrap().parts[0].1,
+                CompoundSelector {
+                    nth_child: Some(1),
+                    ..CompoundSelector::default()
+                }
+            );
+        }
+
+        #[test]
+        fn parses_attribute_operators() {
+            let selector = Selector::parse(
+                r#"a[data-x][href^="https:"][ rel = 'a b' ][title$=end][class*=ad]"#,
+            )
+            .unwrap();
+            let attribute = |name: &str, operator| AttributeSelector {
+                name: name.to_string(),
+                operator,
+            };
+
+            assert_eq!(
+                selector.parts[0].1.attributes,
+                vec![
+                    attribute("data-x", AttributeOperator::Exists),
+                    attribute("href", AttributeOperator::StartsWith("https:".to_string())),
+                    attribute("rel", AttributeOperator::Equals("a b".to_string())),
+                    attribute("title", AttributeOperator::EndsWith("end".to_string())),
+                    attribute("class", AttributeOperator::Contains("ad".to_string())),
+                ]
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_selectors() {
+            for selector in [
+                "",
+                "> a",
+                "a >",
+                "div..a",
+                "a$",
+                "a[href",
+                r#"a[href="x]"#,
+                "a[href~=x]",
+                "li:nth-child(0)",
+                "li:nth-child(n)",
+                "a:hover",
+            ] {
+                assert!(Selector::parse(selector).is_err(), "{}", selector);
+            }
+        }
+
+        #[test]
+        fn parses_field_selectors() {
+            assert_eq!(
+                FieldSelector::parse("h2 a@href").unwrap(),
+                FieldSelector {
+                    selector: Some(Selector::parse("h2 a").unwrap()),
+                    attribute: Some("href".to_string()),
+                }
+            );
+            assert_eq!(
+                FieldSelector::parse("@data-url").unwrap(),
+                FieldSelector {
+                    selector: None,
+                    attribute: Some("data-url".to_string()),
+                }
+            );
+            // Not an attribute name, so part of the selector
+            assert!(FieldSelector::parse("a@").is_err());
+            assert!(FieldSelector::parse("").is_err());
+        }
+
+        #[test]
+        fn matches_combinators() {
+            let html = r#"<div class="results">
+                <div id="a"><p>A</p><div id="nested"><p>B</p></div></div>
+                <section><div id="c"><p>C</p></div></section>
+            </div>"#;
+
+            assert_eq!(matching_ids(".results > div", html), vec!["a".to_string()]);
+            // Results don't nest, so the nested element is part of the first result
+            assert_eq!(matching_ids(".results div", html), vec!["a", "c"]);
+            assert_eq!(matching_ids("section > div", html), vec!["c"]);
+            assert!(matching_ids("div > section > p", html).is_empty());
+        }
+
+        #[test]
+        fn matches_positions() {
+            let html = r#"<ul>
+                <li id="a"><p>A</p></li>
+                <br>
+                <li id="b"><p>B</p></li>
+                <li id="c"><p>C</p>
+            </ul>"#;
+
+            assert_eq!(matching_ids("li:first-child", html), vec!["a"]);
+            assert_eq!(matching_ids("li:nth-child(3)", html), vec!["b"]);
+            assert_eq!(matching_ids("li:nth-of-type(3)", html), vec!["c"]);
+            assert!(matching_ids("br:first-of-type:nth-child(1)", html).is_empty());
+        }
+
+        #[test]
+        fn matches_attribute_operators() {
+            let html = r#"<div>
+                <a id="a" href="https://example.com/a" class="result ad"><p>A</p></a>
+                <a id="b" href="/b" class="result" data-new><p>B</p></a>
+                <a id="c" href='https://example.com/c.pdf' class="results"><p>C</p></a>
+            </div>"#;
+
+            assert_eq!(matching_ids("a[data-new]", html), vec!["b"]);
+            assert_eq!(matching_ids(r#"a[href^="https:"]"#, html), vec!["a", "c"]);
+            assert_eq!(matching_ids("a[href$='.pdf']", html), vec!["c"]);
+            assert_eq!(matching_ids("a[class*=ad]", html), vec!["a"]);
+            assert_eq!(matching_ids("a[class=result]", html), vec!["b"]);
+            // Classes are matched as whole words
+            assert_eq!(matching_ids("a.result", html), vec!["a", "b"]);
+        }
+    }
+}
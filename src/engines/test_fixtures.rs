@@ -0,0 +1,93 @@
+// Example responses and helpers shared by the tests
+pub mod test_fixtures {
+    use std::{fs, path::PathBuf, time::SystemTime};
+
+    use chrono::DateTime;
+
+    use crate::{
+        config::config::parse_config,
+        engines::{
+            configurable::configurable::ConfigurableEngine,
+            engine_base::engine_base::{EngineBase, SearchEngine, SearchResult},
+        },
+    };
+
+    /// Creates a fresh parser of an engine for every response
+    pub type NewParser<'a> = Box<dyn Fn() -> Box<dyn EngineBase> + 'a>;
+
+    pub const BRAVE_EXAMPLE: &[u8] = include_bytes!("./brave_example.html");
+    pub const DUCKDUCKGO_EXAMPLE: &[u8] = include_bytes!("./duckduckgo_example.html");
+    /// Hand-written in the markup Bing uses, including ads, answers and sitelinks
+    pub const BING_EXAMPLE: &[u8] = include_bytes!("./bing_example.html");
+    /// Response of an engine that is only defined in the config
+    pub const MOJEEK_EXAMPLE: &[u8] = include_bytes!("./mojeek_example.html");
+
+    /// `MOJEEK_EXAMPLE` parsed using regexes
+    pub const MOJEEK_REGEX_ENGINE: &str = r#"
+        [[engine]]
+        name = "Mojeek"
+        url = "https://www.mojeek.com/search"
+        results_start = '<div class="results-count">'
+        single_result = '<li.*?<a href="(?P<url>.+?)".*?>(?P<title>.+?)</a>.*?<p class="s">(?P<description>.+?)</p>'
+    "#;
+
+    /// `MOJEEK_EXAMPLE` parsed using selectors
+    pub const MOJEEK_SELECTOR_ENGINE: &str = r#"
+        [[engine]]
+        name = "Mojeek"
+        url = "https://www.mojeek.com/search"
+        date_format = "%d %b %Y"
+
+        [engine.selectors]
+        result = "ul.results-standard > li"
+        title = "h2 a"
+        url = "h2 a@href"
+        description = "p.s"
+        image = "img.favicon@src"
+        date = "span.date"
+        breadcrumbs = "p.i"
+    "#;
+
+    /// Relative dates depend on the time of parsing, so only their presence is compared
+    pub fn comparable(mut result: SearchResult) -> SearchResult {
+        if let Some(date) = &mut result.date {
+            if date.is_relative {
+                date.date = DateTime::UNIX_EPOCH;
+            }
+        }
+
+        result
+    }
+
+    /// The first engine defined in `config`
+    pub fn configured_engine(config: &str) -> ConfigurableEngine {
+        parse_config(config).unwrap().engines.remove(0)
+    }
+
+    /// Result without a description or extras, titled by its url
+    pub fn search_result(engine: SearchEngine, url: &str, position: u32) -> SearchResult {
+        SearchResult {
+            title: url.to_string(),
+            url: url.to_string(),
+            description: String::new(),
+            engine,
+            image_url: None,
+            date: None,
+            extras: Default::default(),
+            position,
+        }
+    }
+
+    /// Path of a directory that is unique to a test and doesn't exist yet
+    pub fn temporary_directory(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let directory =
+            std::env::temp_dir().join(format!("tifsep-{}-{}-{}", name, std::process::id(), nanos));
+        let _ = fs::remove_dir_all(&directory);
+
+        directory
+    }
+}
@@ -2,7 +2,12 @@
 // This module differs from utils in the way that everything here
 // is specifically related the project
 pub mod helpers {
+    use std::ops::{Deref, DerefMut};
+
     use reqwest::{Client, ClientBuilder};
+    use tokio::task::JoinHandle;
+
+    use crate::engines::engine_base::engine_base::SearchEngine;
 
     const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.3";
 
@@ -12,4 +17,40 @@ pub mod helpers {
             .build()
             .unwrap()
     }
+
+    pub type EngineTask = JoinHandle<()>;
+
+    /// Engine tasks of a single search.
+    ///
+    /// All tasks that are still running are aborted when this is dropped, e.g. when
+    /// the browser disconnects and rocket drops the response stream owning it.
+    pub struct EngineTasks(Vec<(SearchEngine, EngineTask)>);
+
+    impl EngineTasks {
+        pub fn new(tasks: Vec<(SearchEngine, EngineTask)>) -> Self {
+            Self(tasks)
+        }
+    }
+
+    impl Deref for EngineTasks {
+        type Target = [(SearchEngine, EngineTask)];
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl DerefMut for EngineTasks {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.0
+        }
+    }
+
+    impl Drop for EngineTasks {
+        fn drop(&mut self) {
+            for (_, task) in &self.0 {
+                task.abort();
+            }
+        }
+    }
 }
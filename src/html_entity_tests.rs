@@ -0,0 +1,65 @@
+// Tests of the character reference decoding, mostly taken from the examples in
+// https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+pub mod html_entity_tests {
+    use crate::utils::utils::{decode_html_attribute, decode_html_entities};
+
+    #[test]
+    fn named_references() {
+        assert_eq!(decode_html_entities("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(decode_html_entities("&lt;b&gt;"), "<b>");
+        assert_eq!(decode_html_entities("&quot;&apos;"), "\"'");
+        assert_eq!(decode_html_entities("&hellip;&mdash;&euro;"), "…—€");
+        assert_eq!(
+            decode_html_entities("&CounterClockwiseContourIntegral;"),
+            "∳"
+        );
+        // Some references stand for two code points
+        assert_eq!(decode_html_entities("&fjlig;"), "fj");
+        assert_eq!(decode_html_entities("&NotEqualTilde;"), "\u{2242}\u{0338}");
+    }
+
+    #[test]
+    fn unknown_references_are_kept() {
+        assert_eq!(decode_html_entities("&unknown;"), "&unknown;");
+        assert_eq!(decode_html_entities("AT&T"), "AT&T");
+        assert_eq!(decode_html_entities("& &; &#; &#x;"), "& &; &#; &#x;");
+        assert_eq!(decode_html_entities("&&amp;"), "&&");
+    }
+
+    #[test]
+    fn legacy_references_without_semicolon() {
+        assert_eq!(decode_html_entities("&amp &copy 2024"), "& © 2024");
+        assert_eq!(decode_html_entities("&notit;"), "¬it;");
+        assert_eq!(decode_html_entities("&notin;"), "∉");
+        assert_eq!(decode_html_entities("&ampere"), "&ere");
+        // Only legacy references may omit the semicolon
+        assert_eq!(decode_html_entities("&hellip"), "&hellip");
+    }
+
+    #[test]
+    fn legacy_references_in_attributes() {
+        assert_eq!(
+            decode_html_attribute("/search?q=a&copy=1&amp;b=2&lang=en"),
+            "/search?q=a&copy=1&b=2&lang=en"
+        );
+        assert_eq!(decode_html_attribute("a&lt b"), "a< b");
+        assert_eq!(decode_html_attribute("&notin;"), "∉");
+    }
+
+    #[test]
+    fn numeric_references() {
+        assert_eq!(decode_html_entities("&#39;&#x27;&#X27;"), "'''");
+        assert_eq!(decode_html_entities("&#128512;"), "😀");
+        // The semicolon is optional
+        assert_eq!(decode_html_entities("&#65 &#x42"), "A B");
+        // Digits are consumed greedily
+        assert_eq!(decode_html_entities("&#x42C"), "Ь");
+        assert_eq!(decode_html_entities("&#0;"), "\u{FFFD}");
+        assert_eq!(decode_html_entities("&#xD800;"), "\u{FFFD}");
+        assert_eq!(decode_html_entities("&#x110000;"), "\u{FFFD}");
+        assert_eq!(decode_html_entities("&#99999999999999999999;"), "\u{FFFD}");
+        // C1 controls are decoded as windows-1252
+        assert_eq!(decode_html_entities("&#128;&#x99;"), "€™");
+        assert_eq!(decode_html_entities("&#x81;"), "\u{81}");
+    }
+}
@@ -1,26 +1,55 @@
 use std::str;
-
-use ahash::AHashSet;
-use engines::bing::bing::Bing;
-use engines::brave::brave::Brave;
-use engines::duckduckgo::duckduckgo::DuckDuckGo;
-use engines::engine_base::engine_base::SearchResult;
+use std::sync::Arc;
+
+use ahash::{AHashMap, AHashSet};
+use cache::cache::ResultCache;
+use config::config::{load_config, AppConfig};
+use engines::drift::drift::ParserDriftMonitor;
+use engines::engine_base::engine_base::{SearchEngine, SearchEvent, SearchResult};
+use engines::rate_limit::rate_limit::RateLimiter;
+use engines::registry::registry::EngineRegistry;
 use lazy_static::lazy_static;
+use ranking::ranking::{FusedResults, RankFusion};
+use recording::recording::ResponseTransport;
 use rocket::form::Form;
 use rocket::response::content::{RawCss, RawHtml};
 use rocket::response::stream::TextStream;
 use rocket::time::Instant;
+use rocket::State;
+use search::search::InflightSearches;
 use static_files::static_files::{
-    render_beginning_html, render_finished_css, render_result, render_result_engine_visibility,
+    render_beginning_html, render_cached_css, render_cached_notice, render_engine_error_css,
+    render_failed_css, render_final_results, render_finished_css, render_first_byte_css,
+    render_result, render_result_engine_visibility, render_result_order_css, render_status_html,
 };
-use tokio::sync::mpsc;
+use tokio::time;
+use tracking::tracking::UrlCleaner;
 
 use crate::static_files::static_files::read_file_contents;
 
+pub mod cache;
+#[cfg(test)]
+pub mod cache_tests;
 pub mod client;
+pub mod config;
+#[cfg(test)]
+pub mod config_tests;
 pub mod engines;
 pub mod helpers;
+pub mod html_entities;
+#[cfg(test)]
+pub mod html_entity_tests;
+pub mod ranking;
+#[cfg(test)]
+pub mod ranking_tests;
+pub mod recording;
+pub mod search;
+#[cfg(test)]
+pub mod search_tests;
 pub mod static_files;
+pub mod tracking;
+#[cfg(test)]
+pub mod tracking_tests;
 pub mod tsclient;
 pub mod utils;
 
@@ -42,60 +71,162 @@ async fn search_get() -> RawHtml<&'static str> {
     RawHtml(include_str!("./public/html/frontpage.html"))
 }
 
+/// Health of every engine, to notice engines that changed their markup
+#[get("/status")]
+fn status_get(registry: &State<EngineRegistry>) -> RawHtml<String> {
+    let engines = registry
+        .kinds()
+        .into_iter()
+        .map(|kind| {
+            (
+                kind,
+                registry.drift.status(&kind),
+                registry.cooldowns.is_cooling_down(&kind),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    RawHtml(render_status_html(&engines))
+}
+
 #[derive(FromForm)]
 struct Body {
     query: String,
+    /// Ignore cached results
+    #[field(default = false)]
+    refresh: bool,
+    /// Instead of moving results while engines are still searching, show them in
+    /// their final order once all engines finished
+    #[field(default = false)]
+    final_order: bool,
 }
 
-macro_rules! search {
-    ($engine:ident,$query_ref:expr,$tx_ref:expr) => {{
-        tokio::spawn(async move {
-            let mut engine = $engine::new();
+/// Render a result, or only its engine badge if the result has already been rendered.
+/// Results are the same if their canonical urls are.
+fn render_search_result(
+    result: &SearchResult,
+    rendered_urls: &mut AHashSet<String>,
+    engines: &[SearchEngine],
+) -> String {
+    let engine_visibility = render_result_engine_visibility(&result.get_html_id(), &result.engine);
 
-            engine.search($query_ref, $tx_ref).await
-        })
-    }};
+    if !rendered_urls.insert(result.canonical_url()) {
+        return engine_visibility;
+    }
+
+    format!("{}{}", render_result(result, engines), engine_visibility)
 }
 
 #[post("/", data = "<body>")]
-async fn search_post<'a>(body: Form<Body>) -> RawHtml<TextStream![String]> {
+async fn search_post(
+    body: Form<Body>,
+    registry: &State<EngineRegistry>,
+    inflight_searches: &State<InflightSearches>,
+    url_cleaner: &State<UrlCleaner>,
+) -> RawHtml<TextStream![String]> {
     let query = &body.query;
-    let query_brave = query.to_owned().clone();
-    let query_duckduckgo = query.to_owned().clone();
-    let query_bing = query.to_owned().clone();
 
     let mut first_result_yielded = false;
     let first_result_start = Instant::now();
 
-    let (tx, mut rx) = mpsc::channel::<SearchResult>(16);
-    let tx_brave = tx.clone();
-    let tx_duckduckgo = tx.clone();
-    let tx_bing = tx.clone();
-
-    let mut bing_finished_informed = false;
-    let mut brave_finished_informed = false;
-    let mut duckduckgo_finished_informed = false;
-
-    let now = Instant::now();
-
-    let brave_task = search!(Brave, &query_brave, tx_brave);
-    let bing_task = search!(Bing, &query_bing, tx_bing);
-    let duckduckgo_task = search!(DuckDuckGo, &query_duckduckgo, tx_duckduckgo);
-
-    let beginning_html = render_beginning_html(&query);
+    let deadline = time::Instant::now() + registry.query_timeout;
+
+    let cached = match body.refresh {
+        true => None,
+        false => inflight_searches.cached(registry, query).await,
+    };
+    let cached_notice = cached
+        .as_ref()
+        .map(|cached| render_cached_notice(query, &cached.created_at, body.final_order));
+
+    // Owned by the stream below, so closing the page aborts all engine requests,
+    // unless another request for the same query is still subscribed
+    let search = match cached {
+        Some(_) => None,
+        None => Some(inflight_searches.subscribe(registry, query)),
+    };
+    let engine_kinds = registry.kinds();
+    let url_cleaner = url_cleaner.inner().clone();
+    let ranking = registry.ranking.clone();
+    let final_order = body.final_order;
+    // Engines whose final status has already been rendered
+    let mut finished_engines: AHashSet<SearchEngine> = AHashSet::new();
+
+    let beginning_html = render_beginning_html(&query, &engine_kinds, body.final_order);
 
     let mut results: AHashSet<String> = AHashSet::new();
 
     RawHtml(TextStream! {
         yield beginning_html;
 
-        while !brave_task.is_finished() || !duckduckgo_task.is_finished() || !bing_task.is_finished() {
-            while let Some(result) = rx.recv().await {
-                if results.contains(&result.url) {
-                    yield render_result_engine_visibility(&result.get_html_id(), &result.engine);
+        if let (Some(cached), Some(cached_notice)) = (cached, cached_notice) {
+            yield cached_notice;
+            yield "<style>.fake { display: none; }</style>".to_string();
 
-                    continue;
-                }
+            for kind in engine_kinds.iter() {
+                yield render_cached_css(kind);
+            }
+
+            // All results are known already, so they are shown in their final order
+            let cached_results = cached
+                .results
+                .iter()
+                .map(|result| url_cleaner.clean_result(result))
+                .collect();
+
+            for result in ranking.sort(cached_results).iter() {
+                yield render_search_result(result, &mut results, &engine_kinds);
+            }
+        }
+
+        if let Some(mut search) = search {
+            let mut fused_results = FusedResults::new(&ranking);
+            // CSS `order` last sent for each result
+            let mut orders: AHashMap<String, u32> = AHashMap::new();
+
+            loop {
+                let event = match time::timeout_at(deadline, search.next()).await {
+                    Ok(Some(event)) => event,
+                    // All engines are done
+                    Ok(None) => break,
+                    Err(_) => {
+                        for kind in engine_kinds.iter() {
+                            if !finished_engines.contains(kind) {
+                                finished_engines.insert(*kind);
+
+                                yield render_failed_css(kind, "timed out");
+                            }
+                        }
+
+                        break;
+                    }
+                };
+
+                let result = match event {
+                    SearchEvent::Started { .. } => continue,
+                    SearchEvent::FirstByte { engine } => {
+                        yield render_first_byte_css(&engine);
+
+                        continue;
+                    }
+                    SearchEvent::Finished { engine, duration, count } => {
+                        finished_engines.insert(engine);
+
+                        yield render_finished_css(&engine, duration.as_millis(), count);
+
+                        continue;
+                    }
+                    SearchEvent::Failed { engine, error } => {
+                        finished_engines.insert(engine);
+
+                        yield render_engine_error_css(&engine, &error);
+
+                        continue;
+                    }
+                    // Cleaned before deduplication, so that links differing only in
+                    // tracking parameters are merged
+                    SearchEvent::Result(result) => url_cleaner.clean_result(&result),
+                };
 
                 if !first_result_yielded {
                     let diff = first_result_start.elapsed().whole_milliseconds();
@@ -105,53 +236,88 @@ async fn search_post<'a>(body: Form<Body>) -> RawHtml<TextStream![String]> {
                     yield "<style>.fake { display: none; }</style>".to_string();
                 }
 
-                if !bing_finished_informed && bing_task.is_finished() {
-                    bing_finished_informed = true;
-
-                    yield render_finished_css("bing", now.elapsed().whole_milliseconds());
-                }
-
-                if !brave_finished_informed && brave_task.is_finished() {
-                    brave_finished_informed = true;
+                // Results are shown right away and, unless they're sorted at the end,
+                // moved whenever their score changes, e.g. because another engine found them too
+                let id = result.get_html_id();
+                let order = fused_results.push(&result).order();
 
-                    yield render_finished_css("brave", now.elapsed().whole_milliseconds());
+                if !final_order && orders.insert(id.clone(), order) != Some(order) {
+                    yield render_result_order_css(&id, order);
                 }
 
-                if !duckduckgo_finished_informed && duckduckgo_task.is_finished() {
-                    duckduckgo_finished_informed = true;
+                yield render_search_result(&result, &mut results, &engine_kinds);
+            }
 
-                    yield render_finished_css("duckduckgo", now.elapsed().whole_milliseconds());
+            // Engines whose task ended without reporting a final status have panicked
+            for kind in engine_kinds.iter() {
+                if !finished_engines.contains(kind) {
+                    yield render_failed_css(kind, "failed: crashed");
                 }
-
-                yield render_result(&result);
-                yield render_result_engine_visibility(&result.get_html_id(), &result.engine);
-
-                results.insert(result.url.to_string());
             }
-        }
 
-        let diff = first_result_start.elapsed().whole_milliseconds();
+            if final_order && !fused_results.is_empty() {
+                let ranked = fused_results.ranked();
 
-        if !bing_finished_informed {
-            yield render_finished_css("bing", now.elapsed().whole_milliseconds());
-        }
+                yield render_final_results(ranked.into_iter(), &engine_kinds);
+            }
 
-        if !brave_finished_informed {
-            yield render_finished_css("brave", now.elapsed().whole_milliseconds());
+            // Abort the engine requests as soon as possible if nobody else is subscribed
+            drop(search);
         }
 
-        if !duckduckgo_finished_informed {
-            yield render_finished_css("duckduckgo", now.elapsed().whole_milliseconds());
-        }
+        let diff = first_result_start.elapsed().whole_milliseconds();
 
         yield format!("<strong>End taken: {}ms</strong>", diff);
         yield HTML_END.to_string();
     })
 }
 
+fn build_engine_registry(config: AppConfig) -> EngineRegistry {
+    let mut registry = EngineRegistry::with_default_engines();
+
+    for engine in config.engines {
+        registry.register(Arc::new(engine));
+    }
+
+    if let Some(timeout) = config.query_timeout {
+        registry.query_timeout = timeout;
+    }
+
+    if let Some(timeout) = config.default_engine_timeout {
+        registry.default_engine_timeout = timeout;
+    }
+
+    for (engine, timeout) in config.engine_timeouts {
+        registry.set_engine_timeout(engine, timeout);
+    }
+
+    registry.rate_limiter = Arc::new(RateLimiter::new(
+        config.default_rate_limit.unwrap_or_default(),
+        config.rate_limits.into_iter().collect(),
+    ));
+    registry.drift = Arc::new(ParserDriftMonitor::new(config.drift));
+    registry.ranking = Arc::new(RankFusion::new(&config.ranking));
+
+    registry
+}
+
 #[launch]
 async fn rocket() -> _ {
+    let config = load_config().expect("Could not load config");
+    let cache = ResultCache::from_config(&config.cache).expect("Could not create result cache");
+    let url_cleaner =
+        UrlCleaner::from_config(&config.url_cleaning).expect("Could not load tracking rules");
+    let transport = ResponseTransport::from_config(&config.recording)
+        .await
+        .expect("Could not start response replay");
+
+    let mut registry = build_engine_registry(config);
+    registry.transport = Arc::new(transport);
+
     rocket::build()
-        .mount("/", routes![search_post, search_get])
+        .manage(registry)
+        .manage(InflightSearches::new(Arc::new(cache)))
+        .manage(url_cleaner)
+        .mount("/", routes![search_post, search_get, status_get])
         .mount("/", routes![get_tailwindcss])
 }
@@ -0,0 +1,185 @@
+// Ordering of the results of all engines using reciprocal rank fusion.
+//
+// Every engine that found a result adds `weight / (k + position)` to its score, so
+// results that several engines rank highly come first, and one engine's top result
+// beats another engine's tenth. `k` dampens the advantage of the top positions.
+pub mod ranking {
+    use std::collections::BTreeMap;
+
+    use ahash::AHashMap;
+    use serde::Deserialize;
+
+    use crate::engines::engine_base::engine_base::{SearchEngine, SearchResult};
+
+    // Scores are scaled to integers for the CSS `order` of results
+    const ORDER_SCALE: f64 = 1_000_000.0;
+    const MAX_ORDER: u32 = 1_000_000_000;
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(default)]
+    pub struct RankingConfig {
+        pub k: f64,
+        /// Weights of engines by name, engines that aren't listed have a weight of 1
+        pub weights: BTreeMap<String, f64>,
+    }
+
+    impl Default for RankingConfig {
+        fn default() -> Self {
+            Self {
+                // As proposed by Cormack et al., who introduced reciprocal rank fusion
+                k: 60.0,
+                weights: BTreeMap::new(),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct RankFusion {
+        k: f64,
+        weights: AHashMap<SearchEngine, f64>,
+    }
+
+    impl Default for RankFusion {
+        fn default() -> Self {
+            Self::new(&RankingConfig::default())
+        }
+    }
+
+    impl RankFusion {
+        pub fn new(config: &RankingConfig) -> Self {
+            Self {
+                k: config.k.max(0.0),
+                weights: config
+                    .weights
+                    .iter()
+                    .map(|(name, weight)| (SearchEngine::from_name(name), weight.max(0.0)))
+                    .collect(),
+            }
+        }
+
+        pub fn weight(&self, engine: &SearchEngine) -> f64 {
+            self.weights.get(engine).copied().unwrap_or(1.0)
+        }
+
+        /// Score of a result at `position` of `engine`.
+        /// Results without a position, e.g. cached before positions existed, add nothing.
+        pub fn score(&self, engine: &SearchEngine, position: u32) -> f64 {
+            match position {
+                0 => 0.0,
+                position => self.weight(engine) / (self.k + position as f64),
+            }
+        }
+
+        /// Order `results` by the fused score of their canonical url, keeping the
+        /// results of all engines so that each can still be attributed
+        pub fn sort(&self, results: Vec<SearchResult>) -> Vec<SearchResult> {
+            let mut fused = FusedResults::new(self);
+
+            for result in results.iter() {
+                fused.push(result);
+            }
+
+            let ranks = fused
+                .ranked()
+                .into_iter()
+                .enumerate()
+                .map(|(rank, result)| (result.canonical_url.clone(), rank))
+                .collect::<AHashMap<_, _>>();
+
+            let mut results = results;
+            results.sort_by_key(|result| ranks.get(&result.canonical_url()).copied());
+
+            results
+        }
+    }
+
+    /// A result together with every engine that found it
+    #[derive(Clone, Debug)]
+    pub struct FusedResult {
+        /// The result as found by the first engine
+        pub result: SearchResult,
+        pub canonical_url: String,
+        /// Position of the result on each engine that found it
+        pub positions: BTreeMap<SearchEngine, u32>,
+        pub score: f64,
+    }
+
+    impl FusedResult {
+        /// CSS `order` of the result in the list, lower for better scores. It only
+        /// depends on the result's own score, so a new score only moves this result.
+        /// Always positive, so that other items of the list stay above the results.
+        pub fn order(&self) -> u32 {
+            let scaled = (self.score * ORDER_SCALE)
+                .round()
+                .clamp(0.0, (MAX_ORDER - 1) as f64);
+
+            MAX_ORDER - scaled as u32
+        }
+    }
+
+    /// The results of a search merged by their canonical url
+    #[derive(Debug)]
+    pub struct FusedResults<'a> {
+        fusion: &'a RankFusion,
+        indices: AHashMap<String, usize>,
+        // In the order they were found
+        results: Vec<FusedResult>,
+    }
+
+    impl<'a> FusedResults<'a> {
+        pub fn new(fusion: &'a RankFusion) -> Self {
+            Self {
+                fusion,
+                indices: AHashMap::new(),
+                results: Vec::new(),
+            }
+        }
+
+        /// Add a result of an engine and return the merged result it belongs to
+        pub fn push(&mut self, result: &SearchResult) -> &FusedResult {
+            let canonical_url = result.canonical_url();
+            let index = *self
+                .indices
+                .entry(canonical_url.clone())
+                .or_insert_with(|| {
+                    self.results.push(FusedResult {
+                        result: result.clone(),
+                        canonical_url,
+                        positions: BTreeMap::new(),
+                        score: 0.0,
+                    });
+
+                    self.results.len() - 1
+                });
+
+            let fused = &mut self.results[index];
+            let position = fused.positions.entry(result.engine).or_insert(0);
+
+            // Engines sometimes list a page twice, only its best position counts
+            if *position == 0 || (result.position != 0 && result.position < *position) {
+                *position = result.position;
+            }
+
+            fused.score = fused
+                .positions
+                .iter()
+                .map(|(engine, position)| self.fusion.score(engine, *position))
+                .sum();
+
+            fused
+        }
+
+        /// All merged results, best score first; results with the same score stay in
+        /// the order they were found
+        pub fn ranked(&self) -> Vec<&FusedResult> {
+            let mut ranked = self.results.iter().collect::<Vec<_>>();
+            ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+            ranked
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.results.is_empty()
+        }
+    }
+}
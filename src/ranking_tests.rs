@@ -0,0 +1,155 @@
+// Tests of the ordering of results using reciprocal rank fusion
+pub mod ranking_tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        engines::{
+            engine_base::engine_base::{SearchEngine, SearchResult},
+            test_fixtures::test_fixtures::search_result,
+        },
+        ranking::ranking::{FusedResults, RankFusion, RankingConfig},
+        static_files::static_files::{render_final_results, render_result},
+    };
+
+    fn urls(results: &[SearchResult]) -> Vec<(SearchEngine, &str)> {
+        results
+            .iter()
+            .map(|result| (result.engine, result.url.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn results_found_by_several_engines_come_first() {
+        let results = vec![
+            search_result(SearchEngine::Bing, "https://a.example/", 1),
+            search_result(SearchEngine::Bing, "https://b.example/", 2),
+            search_result(SearchEngine::Brave, "https://c.example/", 1),
+            search_result(SearchEngine::Brave, "https://www.b.example/", 2),
+            search_result(SearchEngine::DuckDuckGo, "https://b.example/", 3),
+        ];
+
+        assert_eq!(
+            urls(&RankFusion::default().sort(results)),
+            [
+                (SearchEngine::Bing, "https://b.example/"),
+                (SearchEngine::Brave, "https://www.b.example/"),
+                (SearchEngine::DuckDuckGo, "https://b.example/"),
+                (SearchEngine::Bing, "https://a.example/"),
+                (SearchEngine::Brave, "https://c.example/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn engine_weights() {
+        let fusion = RankFusion::new(&RankingConfig {
+            weights: BTreeMap::from([("brave".to_string(), 2.0)]),
+            ..RankingConfig::default()
+        });
+        let results = vec![
+            search_result(SearchEngine::Bing, "https://a.example/", 1),
+            search_result(SearchEngine::Brave, "https://b.example/", 3),
+        ];
+
+        assert_eq!(fusion.weight(&SearchEngine::Bing), 1.0);
+        assert_eq!(fusion.score(&SearchEngine::Brave, 3), 2.0 / 63.0);
+        assert_eq!(
+            urls(&fusion.sort(results)),
+            [
+                (SearchEngine::Brave, "https://b.example/"),
+                (SearchEngine::Bing, "https://a.example/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fused_positions() {
+        let fusion = RankFusion::default();
+        let mut fused = FusedResults::new(&fusion);
+
+        fused.push(&search_result(SearchEngine::Bing, "https://a.example/", 4));
+        fused.push(&search_result(
+            SearchEngine::Bing,
+            "https://a.example/#top",
+            2,
+        ));
+        let merged = fused
+            .push(&search_result(SearchEngine::Brave, "http://a.example/", 1))
+            .clone();
+
+        assert_eq!(merged.result.engine, SearchEngine::Bing);
+        assert_eq!(
+            merged.positions,
+            BTreeMap::from([(SearchEngine::Bing, 2), (SearchEngine::Brave, 1)])
+        );
+        assert_eq!(merged.score, 1.0 / 62.0 + 1.0 / 61.0);
+
+        // Results without a position keep the order they were found in
+        fused.push(&search_result(
+            SearchEngine::DuckDuckGo,
+            "https://b.example/",
+            0,
+        ));
+        fused.push(&search_result(
+            SearchEngine::DuckDuckGo,
+            "https://c.example/",
+            0,
+        ));
+
+        assert_eq!(
+            fused
+                .ranked()
+                .iter()
+                .map(|result| result.canonical_url.as_str())
+                .collect::<Vec<_>>(),
+            ["a.example/", "b.example/", "c.example/"]
+        );
+    }
+
+    #[test]
+    fn css_order_follows_score() {
+        let fusion = RankFusion::default();
+        let mut fused = FusedResults::new(&fusion);
+
+        let first = fused
+            .push(&search_result(SearchEngine::Bing, "https://a.example/", 1))
+            .order();
+        let second = fused
+            .push(&search_result(SearchEngine::Bing, "https://b.example/", 2))
+            .order();
+        // Confirmed by another engine, so it moves above the first
+        let confirmed = fused
+            .push(&search_result(SearchEngine::Brave, "https://b.example/", 5))
+            .order();
+        let unranked = fused
+            .push(&search_result(SearchEngine::Brave, "https://c.example/", 0))
+            .order();
+
+        assert!(first < second);
+        assert!(confirmed < first);
+        assert!(unranked > second);
+        assert!(confirmed > 0);
+    }
+
+    #[test]
+    fn final_results_have_their_own_ids() {
+        let fusion = RankFusion::default();
+        let mut fused = FusedResults::new(&fusion);
+        let engines = [SearchEngine::Bing, SearchEngine::Brave];
+
+        let bing = search_result(SearchEngine::Bing, "https://a.example/", 1);
+        let streamed = render_result(&bing, &engines);
+        fused.push(&bing);
+        fused.push(&search_result(SearchEngine::Brave, "https://a.example/", 2));
+
+        let id = bing.get_html_id();
+        let html = render_final_results(fused.ranked().into_iter(), &engines);
+
+        assert!(streamed.contains(&format!(r#"id="{}""#, id)));
+        assert!(!html.contains(&format!(r#"id="{}""#, id)));
+        assert!(html.contains(&format!(r#"id="final-{}""#, id)));
+        // Both engines are shown on the copy
+        assert!(html.contains(&format!("#final-{} .search-engines .bing", id)));
+        assert!(html.contains(&format!("#final-{} .search-engines .brave", id)));
+    }
+}
@@ -0,0 +1,420 @@
+// Recording of raw upstream responses, and replaying them offline.
+//
+// In record mode, every engine response is saved to the fixture directory as two files,
+// keyed by engine and query: `<engine>-<query hash>.html` contains the raw body, and
+// `<engine>-<query hash>.json` the status, chunk boundaries and arrival times.
+// In replay mode, engine requests are sent to a local HTTP server instead, which serves
+// the recorded responses with their original chunking and timing.
+pub mod recording {
+    use std::{
+        error::Error,
+        fs, mem,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use chrono::{DateTime, Utc};
+    use reqwest::{Client, RequestBuilder, StatusCode, Url};
+    use serde::{Deserialize, Serialize};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        runtime::Handle,
+        task,
+        time::{self, Instant},
+    };
+
+    use crate::{
+        engines::engine_base::engine_base::{EngineError, SearchEngine},
+        utils::utils::hash_string,
+    };
+
+    // Requests to the replay server are tiny, anything larger is not from tifsep
+    const MAX_REPLAY_REQUEST_SIZE: usize = 8 * 1024;
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum RecordingMode {
+        #[default]
+        Off,
+        Record,
+        Replay,
+    }
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(default)]
+    pub struct RecordingConfig {
+        pub mode: RecordingMode,
+        pub directory: PathBuf,
+    }
+
+    impl Default for RecordingConfig {
+        fn default() -> Self {
+            Self {
+                mode: RecordingMode::Off,
+                directory: PathBuf::from("./fixtures"),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct RecordedChunk {
+        /// Offset in the body right after this chunk
+        pub end: usize,
+        /// Time between sending the request and receiving this chunk
+        pub elapsed_ms: u64,
+    }
+
+    /// Everything about a response except its body
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct RecordedResponse {
+        pub engine: SearchEngine,
+        pub query: String,
+        pub url: String,
+        pub status: u16,
+        pub recorded_at: DateTime<Utc>,
+        /// Time between sending the request and receiving the response headers
+        pub headers_ms: u64,
+        pub chunks: Vec<RecordedChunk>,
+    }
+
+    /// Name of the fixture files of a search, without extension
+    pub fn fixture_name(engine: &SearchEngine, query: &str) -> String {
+        format!("{}-{:X}", engine.html_class(), hash_string(query))
+    }
+
+    pub fn save_fixture(
+        directory: &Path,
+        response: &RecordedResponse,
+        body: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let name = fixture_name(&response.engine, &response.query);
+
+        fs::create_dir_all(directory)?;
+        fs::write(directory.join(format!("{}.html", name)), body)?;
+        fs::write(
+            directory.join(format!("{}.json", name)),
+            serde_json::to_string_pretty(response)?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn load_fixture(
+        directory: &Path,
+        name: &str,
+    ) -> Result<(RecordedResponse, Vec<u8>), Box<dyn Error>> {
+        let response: RecordedResponse = serde_json::from_str(&fs::read_to_string(
+            directory.join(format!("{}.json", name)),
+        )?)?;
+        let body = fs::read(directory.join(format!("{}.html", name)))?;
+
+        // Chunks must cover the body in order, as they are sliced out of it when replayed
+        let mut chunk_start = 0;
+
+        for chunk in response.chunks.iter() {
+            if chunk.end < chunk_start || chunk.end > body.len() {
+                return Err(
+                    format!("Fixture {} has an invalid chunk end {}", name, chunk.end).into(),
+                );
+            }
+
+            chunk_start = chunk.end;
+        }
+
+        if chunk_start != body.len() {
+            return Err(format!("Fixture {} doesn't match its body", name).into());
+        }
+
+        Ok((response, body))
+    }
+
+    /// A response that is being recorded.
+    ///
+    /// It's saved by `save`, or when dropped, so that responses of requests that were
+    /// aborted, e.g. because the engine timed out, are kept as well.
+    pub struct ResponseRecording {
+        directory: PathBuf,
+        response: RecordedResponse,
+        body: Vec<u8>,
+        start: Instant,
+        is_saved: bool,
+    }
+
+    impl ResponseRecording {
+        pub fn new(directory: PathBuf, engine: SearchEngine, query: &str) -> Self {
+            Self {
+                directory,
+                response: RecordedResponse {
+                    engine,
+                    query: query.to_string(),
+                    url: String::new(),
+                    status: 0,
+                    recorded_at: Utc::now(),
+                    headers_ms: 0,
+                    chunks: Vec::new(),
+                },
+                body: Vec::new(),
+                start: Instant::now(),
+                is_saved: false,
+            }
+        }
+
+        fn elapsed_ms(&self) -> u64 {
+            self.start.elapsed().as_millis() as u64
+        }
+
+        pub fn record_headers(&mut self, url: &Url, status: StatusCode) {
+            self.response.url = url.to_string();
+            self.response.status = status.as_u16();
+            self.response.headers_ms = self.elapsed_ms();
+        }
+
+        pub fn record_chunk(&mut self, chunk: &[u8]) {
+            self.body.extend_from_slice(chunk);
+            self.response.chunks.push(RecordedChunk {
+                end: self.body.len(),
+                elapsed_ms: self.elapsed_ms(),
+            });
+        }
+
+        /// Save the response on the blocking thread pool and wait until it's written
+        pub async fn save(mut self) {
+            if let Some(save) = self.take_save() {
+                let _ = task::spawn_blocking(save).await;
+            }
+        }
+
+        /// Move the response into a function that saves it, unless it was saved already
+        fn take_save(&mut self) -> Option<impl FnOnce() + Send + 'static> {
+            // Nothing to replay if the request failed before there was a response
+            if self.is_saved || self.response.status == 0 {
+                return None;
+            }
+
+            self.is_saved = true;
+
+            let directory = mem::take(&mut self.directory);
+            let response = self.response.clone();
+            let body = mem::take(&mut self.body);
+
+            Some(move || {
+                if let Err(error) = save_fixture(&directory, &response, &body) {
+                    log::error!(
+                        "Could not record response of {}: {}",
+                        response.engine,
+                        error
+                    );
+                }
+            })
+        }
+    }
+
+    impl Drop for ResponseRecording {
+        fn drop(&mut self) {
+            let Some(save) = self.take_save() else {
+                return;
+            };
+
+            // Aborted requests are dropped on the runtime, which mustn't wait for the disk
+            match Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn_blocking(save);
+                }
+                Err(_) => save(),
+            }
+        }
+    }
+
+    /// How engine requests are sent
+    pub enum ResponseTransport {
+        Live,
+        /// Send requests upstream and record the responses
+        Record {
+            directory: PathBuf,
+        },
+        /// Serve recorded responses from the local replay server
+        Replay {
+            directory: PathBuf,
+            address: SocketAddr,
+        },
+    }
+
+    impl ResponseTransport {
+        pub async fn from_config(config: &RecordingConfig) -> Result<Self, Box<dyn Error>> {
+            Ok(match config.mode {
+                RecordingMode::Off => Self::Live,
+                RecordingMode::Record => Self::Record {
+                    directory: config.directory.clone(),
+                },
+                RecordingMode::Replay => Self::Replay {
+                    directory: config.directory.clone(),
+                    address: start_replay_server(config.directory.clone()).await?,
+                },
+            })
+        }
+
+        /// The request to send instead of `request`
+        pub fn request(
+            &self,
+            client: &Client,
+            engine: &SearchEngine,
+            query: &str,
+            request: RequestBuilder,
+        ) -> Result<RequestBuilder, EngineError> {
+            let Self::Replay { directory, address } = self else {
+                return Ok(request);
+            };
+
+            let name = fixture_name(engine, query);
+
+            if !directory.join(format!("{}.json", name)).exists() {
+                return Err(EngineError::Network(format!(
+                    "No recorded response for \"{}\"",
+                    query
+                )));
+            }
+
+            Ok(client.get(format!("http://{}/{}", address, name)))
+        }
+
+        pub fn recording(&self, engine: &SearchEngine, query: &str) -> Option<ResponseRecording> {
+            match self {
+                Self::Record { directory } => {
+                    Some(ResponseRecording::new(directory.clone(), *engine, query))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    /// Start a local HTTP server that serves the fixtures in `directory`,
+    /// and return its address
+    pub async fn start_replay_server(directory: PathBuf) -> Result<SocketAddr, Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let directory = Arc::new(directory);
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let directory = directory.clone();
+
+                tokio::spawn(async move {
+                    if let Err(error) = replay_response(stream, &directory).await {
+                        log::error!("Could not replay response: {}", error);
+                    }
+                });
+            }
+        });
+
+        Ok(address)
+    }
+
+    /// Read the name of the requested fixture from the request line, e.g. `GET /name HTTP/1.1`
+    async fn read_fixture_name(stream: &mut TcpStream) -> Result<String, Box<dyn Error>> {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let length = stream.read(&mut buffer).await?;
+
+            if length == 0 || request.len() > MAX_REPLAY_REQUEST_SIZE {
+                return Err("Incomplete request".into());
+            }
+
+            request.extend_from_slice(&buffer[..length]);
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let path = request
+            .split_whitespace()
+            .nth(1)
+            .ok_or("Invalid request line")?;
+        let name = path.trim_start_matches('/');
+
+        // Names are generated by `fixture_name`, this keeps requests inside the directory
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid fixture name {}", name).into());
+        }
+
+        Ok(name.to_string())
+    }
+
+    async fn replay_response(
+        mut stream: TcpStream,
+        directory: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let start = Instant::now();
+        // Chunks are written as soon as they are due, so they should arrive separately
+        stream.set_nodelay(true)?;
+
+        let name = read_fixture_name(&mut stream).await?;
+        // Fixtures are read on the blocking thread pool, and `Box<dyn Error>` isn't `Send`,
+        // so the error is returned as a string
+        let directory = directory.to_path_buf();
+        let fixture = task::spawn_blocking(move || {
+            load_fixture(&directory, &name).map_err(|error| error.to_string())
+        })
+        .await
+        .unwrap_or_else(|error| Err(error.to_string()));
+
+        let (response, body) = match fixture {
+            Ok(fixture) => fixture,
+            Err(error) => {
+                stream
+                    .write_all(
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )
+                    .await?;
+
+                return Err(error.into());
+            }
+        };
+
+        let status = StatusCode::from_u16(response.status)?;
+
+        time::sleep_until(start + Duration::from_millis(response.headers_ms)).await;
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 {} {}\r\nContent-Type: text/html; charset=utf-8\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+                    status.as_u16(),
+                    status.canonical_reason().unwrap_or_default(),
+                )
+                .as_bytes(),
+            )
+            .await?;
+
+        let mut chunk_start = 0;
+
+        for chunk in response.chunks.iter() {
+            time::sleep_until(start + Duration::from_millis(chunk.elapsed_ms)).await;
+
+            let data = &body[chunk_start..chunk.end];
+            chunk_start = chunk.end;
+
+            // A chunk of length zero would end the body
+            if data.is_empty() {
+                continue;
+            }
+
+            stream
+                .write_all(format!("{:X}\r\n", data.len()).as_bytes())
+                .await?;
+            stream.write_all(data).await?;
+            stream.write_all(b"\r\n").await?;
+            stream.flush().await?;
+        }
+
+        stream.write_all(b"0\r\n\r\n").await?;
+        stream.flush().await?;
+
+        Ok(())
+    }
+}
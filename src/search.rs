@@ -0,0 +1,307 @@
+// Searches that are shared between all requests for the same query.
+// A second request for a query that is still in flight subscribes to the
+// running search instead of querying every engine again.
+pub mod search {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex, Weak},
+    };
+
+    use ahash::AHashMap;
+    use tokio::sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    };
+
+    use crate::{
+        cache::cache::{CachedSearch, ResultCache},
+        engines::{
+            engine_base::engine_base::{EngineError, SearchEngine, SearchEvent},
+            registry::registry::{run_search, EngineRegistry},
+        },
+        helpers::helpers::EngineTasks,
+    };
+
+    const BROADCAST_CAPACITY: usize = 64;
+
+    /// Events are numbered so that subscribers can skip events they already replayed
+    type IndexedEvent = (usize, SearchEvent);
+
+    struct SearchState {
+        history: Vec<SearchEvent>,
+        // `None` once all engines are done, which closes the broadcast channel
+        sender: Option<broadcast::Sender<IndexedEvent>>,
+    }
+
+    impl SearchState {
+        fn push(&mut self, event: SearchEvent) {
+            let index = self.history.len();
+            self.history.push(event.clone());
+
+            if let Some(sender) = &self.sender {
+                // Fails if there are no subscribers right now, which is fine
+                let _ = sender.send((index, event));
+            }
+        }
+    }
+
+    /// A search that is running on all engines.
+    ///
+    /// The engine tasks are aborted once the last subscriber is dropped.
+    pub struct SharedSearch {
+        engines: Vec<SearchEngine>,
+        state: Arc<Mutex<SearchState>>,
+        _tasks: EngineTasks,
+    }
+
+    impl SharedSearch {
+        fn start(
+            registry: &EngineRegistry,
+            query: &str,
+            key: String,
+            cache: Arc<ResultCache>,
+        ) -> Self {
+            let (tx, rx) = mpsc::channel::<SearchEvent>(16);
+            let mut history = Vec::new();
+
+            // Engines that blocked us recently are skipped until their cooldown is over
+            let (active_engines, skipped_engines): (Vec<_>, Vec<_>) = registry
+                .engines()
+                .iter()
+                .partition(|engine| !registry.cooldowns.is_cooling_down(&engine.kind()));
+
+            for engine in skipped_engines {
+                history.push(SearchEvent::Failed {
+                    engine: engine.kind(),
+                    error: EngineError::Blocked,
+                });
+            }
+
+            let tasks = EngineTasks::new(
+                active_engines
+                    .into_iter()
+                    .map(|engine| {
+                        let kind = engine.kind();
+                        let task = tokio::spawn(run_search(
+                            engine.clone(),
+                            query.to_owned(),
+                            registry.engine_timeout(&kind),
+                            registry.search_context(),
+                            tx.clone(),
+                        ));
+
+                        (kind, task)
+                    })
+                    .collect(),
+            );
+
+            let state = Arc::new(Mutex::new(SearchState {
+                history,
+                sender: Some(broadcast::channel(BROADCAST_CAPACITY).0),
+            }));
+
+            let engines = registry.kinds();
+
+            tokio::spawn(forward_events(
+                rx,
+                state.clone(),
+                engines.clone(),
+                key,
+                cache,
+            ));
+
+            Self {
+                engines,
+                state,
+                _tasks: tasks,
+            }
+        }
+
+        /// All engines taking part in this search, including skipped ones
+        pub fn engines(&self) -> &[SearchEngine] {
+            &self.engines
+        }
+
+        fn is_finished(&self) -> bool {
+            self.state.lock().unwrap().sender.is_none()
+        }
+    }
+
+    /// Move events of the engine tasks into the search's history and to all subscribers.
+    /// Once all engines finished successfully, the results are stored in the cache.
+    async fn forward_events(
+        mut rx: mpsc::Receiver<SearchEvent>,
+        state: Arc<Mutex<SearchState>>,
+        engines: Vec<SearchEngine>,
+        key: String,
+        cache: Arc<ResultCache>,
+    ) {
+        while let Some(event) = rx.recv().await {
+            state.lock().unwrap().push(event);
+        }
+
+        // The lock must not be held while the results are written to the cache
+        let (is_complete, results) = {
+            let mut state = state.lock().unwrap();
+            state.sender = None;
+
+            // Engines that failed, timed out or were aborted only returned part of their results;
+            // caching those would hide the missing ones until the cache expires
+            let is_complete = engines.iter().all(|engine| {
+                state.history.iter().any(|event| match event {
+                    SearchEvent::Finished { engine: other, .. } => other == engine,
+                    _ => false,
+                })
+            });
+
+            let results = state
+                .history
+                .iter()
+                .filter_map(|event| match event {
+                    SearchEvent::Result(result) => Some(result.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            (is_complete, results)
+        };
+
+        if is_complete && !results.is_empty() {
+            cache.insert(&key, results).await;
+        }
+    }
+
+    /// Receives all events of a `SharedSearch`, starting with the ones
+    /// that happened before subscribing
+    pub struct SearchSubscription {
+        search: Arc<SharedSearch>,
+        replay: VecDeque<SearchEvent>,
+        receiver: Option<broadcast::Receiver<IndexedEvent>>,
+        // Index of the next event this subscriber hasn't seen yet
+        position: usize,
+    }
+
+    impl SearchSubscription {
+        fn new(search: Arc<SharedSearch>) -> Self {
+            // The receiver must be created while holding the lock,
+            // so that no event is missed or received twice
+            let state = search.state.lock().unwrap();
+            let replay = state.history.iter().cloned().collect::<VecDeque<_>>();
+            let receiver = state.sender.as_ref().map(|sender| sender.subscribe());
+            let position = replay.len();
+            drop(state);
+
+            Self {
+                search,
+                replay,
+                receiver,
+                position,
+            }
+        }
+
+        pub fn engines(&self) -> &[SearchEngine] {
+            self.search.engines()
+        }
+
+        /// Next event of the search, or `None` once all engines are done
+        pub async fn next(&mut self) -> Option<SearchEvent> {
+            loop {
+                if let Some(event) = self.replay.pop_front() {
+                    return Some(event);
+                }
+
+                match self.receiver.as_mut()?.recv().await {
+                    Ok((index, event)) => {
+                        if index < self.position {
+                            continue;
+                        }
+
+                        self.position = index + 1;
+
+                        return Some(event);
+                    }
+                    // Too slow to keep up with the channel, catch up using the history
+                    Err(RecvError::Lagged(_)) => {
+                        let state = self.search.state.lock().unwrap();
+                        self.replay
+                            .extend(state.history[self.position..].iter().cloned());
+                        self.position = state.history.len();
+                    }
+                    Err(RecvError::Closed) => {
+                        self.receiver = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// All searches that are currently running, keyed by their normalized query
+    pub struct InflightSearches {
+        searches: Mutex<AHashMap<String, Weak<SharedSearch>>>,
+        cache: Arc<ResultCache>,
+    }
+
+    impl InflightSearches {
+        pub fn new(cache: Arc<ResultCache>) -> Self {
+            Self {
+                searches: Mutex::new(AHashMap::new()),
+                cache,
+            }
+        }
+
+        /// Results of a recent search for `query`, if they are still cached
+        pub async fn cached(&self, registry: &EngineRegistry, query: &str) -> Option<CachedSearch> {
+            self.cache
+                .get(&normalize_search_key(query, &registry.kinds()))
+                .await
+        }
+
+        /// Subscribe to the running search for `query`, or start a new one
+        pub fn subscribe(&self, registry: &EngineRegistry, query: &str) -> SearchSubscription {
+            let key = normalize_search_key(query, &registry.kinds());
+            let mut searches = self.searches.lock().unwrap();
+
+            // Clean up searches that have finished or have no subscribers anymore
+            searches.retain(|_, search| match search.upgrade() {
+                Some(search) => !search.is_finished(),
+                None => false,
+            });
+
+            if let Some(search) = searches.get(&key).and_then(|search| search.upgrade()) {
+                return SearchSubscription::new(search);
+            }
+
+            let search = Arc::new(SharedSearch::start(
+                registry,
+                query,
+                key.clone(),
+                self.cache.clone(),
+            ));
+            searches.insert(key, Arc::downgrade(&search));
+
+            SearchSubscription::new(search)
+        }
+    }
+
+    /// Key under which identical searches are coalesced
+    pub fn normalize_search_key(query: &str, engines: &[SearchEngine]) -> String {
+        let mut engines = engines.to_vec();
+        engines.sort();
+
+        let engines = engines
+            .iter()
+            .map(|engine| engine.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{}\n{}",
+            query
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase(),
+            engines
+        )
+    }
+}
@@ -0,0 +1,303 @@
+// Tests of searches that are shared between requests for the same query
+pub mod search_tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use reqwest::{Client, RequestBuilder};
+    use tokio::{
+        sync::{mpsc::Sender, Semaphore},
+        time,
+    };
+
+    use crate::{
+        cache::cache::{MemoryCache, ResultCache},
+        engines::{
+            drift::drift::ParserDriftMonitor,
+            engine_base::engine_base::{EngineBase, EngineError, SearchEngine, SearchEvent},
+            registry::registry::{Engine, EngineRegistry},
+            test_fixtures::test_fixtures::search_result,
+        },
+        recording::recording::ResponseTransport,
+        search::search::{InflightSearches, SearchSubscription},
+    };
+
+    /// Sets its flag when dropped, e.g. when the search owning it is aborted
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Engine that sends `results` results, each once a permit of `gate` is added,
+    /// and then fails with `error` if it is set
+    struct FakeEngine {
+        results: usize,
+        error: Option<EngineError>,
+        gate: Arc<Semaphore>,
+        searches: Arc<AtomicUsize>,
+        aborted: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Engine for FakeEngine {
+        fn kind(&self) -> SearchEngine {
+            SearchEngine::Brave
+        }
+
+        fn parser(&self) -> Box<dyn EngineBase> {
+            unreachable!("the search is faked")
+        }
+
+        fn build_request(&self, client: &Client, query: &str) -> RequestBuilder {
+            client.get(format!("https://example.com/?q={}", query))
+        }
+
+        async fn search(
+            &self,
+            _query: &str,
+            _transport: &ResponseTransport,
+            _drift: &ParserDriftMonitor,
+            tx: Sender<SearchEvent>,
+        ) -> Result<usize, EngineError> {
+            let guard = SetOnDrop(self.aborted.clone());
+            self.searches.fetch_add(1, Ordering::SeqCst);
+
+            for position in 1..=self.results {
+                self.gate.acquire().await.unwrap().forget();
+
+                let url = format!("https://example.com/{}", position);
+                let result = search_result(SearchEngine::Brave, &url, position as u32);
+                if tx.send(SearchEvent::Result(result)).await.is_err() {
+                    return Err(EngineError::Cancelled);
+                }
+            }
+
+            // Finished normally, so it wasn't aborted
+            std::mem::forget(guard);
+
+            match &self.error {
+                Some(error) => Err(error.clone()),
+                None => Ok(self.results),
+            }
+        }
+    }
+
+    struct Setup {
+        registry: EngineRegistry,
+        searches: InflightSearches,
+        gate: Arc<Semaphore>,
+        search_count: Arc<AtomicUsize>,
+        aborted: Arc<AtomicBool>,
+    }
+
+    fn setup(results: usize) -> Setup {
+        setup_failing(results, None)
+    }
+
+    fn setup_failing(results: usize, error: Option<EngineError>) -> Setup {
+        let gate = Arc::new(Semaphore::new(0));
+        let search_count = Arc::new(AtomicUsize::new(0));
+        let aborted = Arc::new(AtomicBool::new(false));
+        let mut registry = EngineRegistry::new();
+        registry.register(Arc::new(FakeEngine {
+            results,
+            error,
+            gate: gate.clone(),
+            searches: search_count.clone(),
+            aborted: aborted.clone(),
+        }));
+
+        Setup {
+            registry,
+            searches: InflightSearches::new(Arc::new(ResultCache::new(
+                Some(Arc::new(MemoryCache::new(8))),
+                Duration::from_secs(60),
+            ))),
+            gate,
+            search_count,
+            aborted,
+        }
+    }
+
+    /// Short description of an event, to compare the events of subscribers
+    fn describe(event: &SearchEvent) -> String {
+        match event {
+            SearchEvent::Started { engine } => format!("started {}", engine),
+            SearchEvent::FirstByte { engine } => format!("first byte {}", engine),
+            SearchEvent::Result(result) => format!("result {}", result.url),
+            SearchEvent::Finished { engine, count, .. } => {
+                format!("finished {} with {}", engine, count)
+            }
+            SearchEvent::Failed { engine, error } => format!("failed {}: {:?}", engine, error),
+        }
+    }
+
+    async fn next(subscription: &mut SearchSubscription) -> Option<String> {
+        time::timeout(Duration::from_secs(5), subscription.next())
+            .await
+            .expect("no event within 5s")
+            .as_ref()
+            .map(describe)
+    }
+
+    async fn remaining(subscription: &mut SearchSubscription) -> Vec<String> {
+        let mut events = Vec::new();
+
+        while let Some(event) = next(subscription).await {
+            events.push(event);
+        }
+
+        events
+    }
+
+    fn expected_events(results: usize) -> Vec<String> {
+        let mut events = vec!["started Brave".to_string()];
+        events.extend(
+            (1..=results).map(|position| format!("result https://example.com/{}", position)),
+        );
+        events.push(format!("finished Brave with {}", results));
+
+        events
+    }
+
+    #[tokio::test]
+    async fn identical_queries_share_a_search() {
+        let setup = setup(2);
+        let mut first = setup.searches.subscribe(&setup.registry, "Shared query");
+        let mut second = setup
+            .searches
+            .subscribe(&setup.registry, "  shared   QUERY ");
+        setup.gate.add_permits(2);
+
+        assert_eq!(remaining(&mut first).await, expected_events(2));
+        assert_eq!(remaining(&mut second).await, expected_events(2));
+        assert_eq!(setup.search_count.load(Ordering::SeqCst), 1);
+
+        // Other queries get their own search
+        let mut other = setup.searches.subscribe(&setup.registry, "other query");
+        setup.gate.add_permits(2);
+
+        assert_eq!(remaining(&mut other).await, expected_events(2));
+        assert_eq!(setup.search_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn late_subscribers_get_earlier_events() {
+        let setup = setup(2);
+        let mut first = setup.searches.subscribe(&setup.registry, "query");
+        setup.gate.add_permits(1);
+
+        assert_eq!(next(&mut first).await.as_deref(), Some("started Brave"));
+        assert_eq!(
+            next(&mut first).await.as_deref(),
+            Some("result https://example.com/1")
+        );
+
+        let mut late = setup.searches.subscribe(&setup.registry, "query");
+        setup.gate.add_permits(1);
+
+        assert_eq!(remaining(&mut late).await, expected_events(2));
+        assert_eq!(remaining(&mut first).await, expected_events(2)[2..]);
+        assert_eq!(setup.search_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_catch_up() {
+        // More results than fit into the broadcast channel
+        let results = 200;
+        let setup = setup(results);
+        let mut fast = setup.searches.subscribe(&setup.registry, "query");
+        let mut slow = setup.searches.subscribe(&setup.registry, "query");
+        setup.gate.add_permits(results);
+
+        assert_eq!(remaining(&mut fast).await, expected_events(results));
+        // Only starts reading once all events were sent
+        assert_eq!(remaining(&mut slow).await, expected_events(results));
+    }
+
+    #[tokio::test]
+    async fn search_is_aborted_without_subscribers() {
+        let setup = setup(2);
+        let mut first = setup.searches.subscribe(&setup.registry, "query");
+        let second = setup.searches.subscribe(&setup.registry, "query");
+
+        assert_eq!(next(&mut first).await.as_deref(), Some("started Brave"));
+
+        drop(first);
+        time::sleep(Duration::from_millis(50)).await;
+        // One subscriber is still left
+        assert!(!setup.aborted.load(Ordering::SeqCst));
+
+        drop(second);
+
+        for _ in 0..100 {
+            if setup.aborted.load(Ordering::SeqCst) {
+                break;
+            }
+
+            time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(setup.aborted.load(Ordering::SeqCst));
+
+        // The aborted search isn't reused
+        let mut new = setup.searches.subscribe(&setup.registry, "query");
+        setup.gate.add_permits(2);
+
+        assert_eq!(remaining(&mut new).await, expected_events(2));
+        assert_eq!(setup.search_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn finished_searches_are_cached() {
+        let setup = setup(2);
+        let mut subscription = setup.searches.subscribe(&setup.registry, "query");
+        setup.gate.add_permits(2);
+        remaining(&mut subscription).await;
+
+        // The results are written to the cache after the last event
+        for _ in 0..100 {
+            if setup
+                .searches
+                .cached(&setup.registry, "query")
+                .await
+                .is_some()
+            {
+                break;
+            }
+
+            time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let cached = setup.searches.cached(&setup.registry, "query").await;
+        assert_eq!(cached.map(|search| search.results.len()), Some(2));
+    }
+
+    #[tokio::test]
+    async fn partial_results_are_not_cached() {
+        let setup = setup_failing(2, Some(EngineError::HttpStatus(500)));
+        let mut subscription = setup.searches.subscribe(&setup.registry, "query");
+        setup.gate.add_permits(2);
+
+        let events = remaining(&mut subscription).await;
+        assert_eq!(
+            events.last().map(String::as_str),
+            Some("failed Brave: HttpStatus(500)")
+        );
+
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(setup
+            .searches
+            .cached(&setup.registry, "query")
+            .await
+            .is_none());
+    }
+}
@@ -6,11 +6,18 @@ pub mod static_files {
         io::{Error, Read},
     };
 
+    use chrono::{DateTime, Utc};
     use reqwest::Url;
 
     use crate::{
-        engines::engine_base::engine_base::{SearchEngine, SearchResult},
-        utils::utils::hash_string,
+        engines::{
+            drift::drift::DriftStatus,
+            engine_base::engine_base::{
+                EngineError, Rating, ResultExtras, SearchEngine, SearchResult,
+            },
+        },
+        ranking::ranking::FusedResult,
+        utils::utils::{decode_url_lossy, escape_css_string, hash_string},
     };
 
     pub fn read_file_contents(path: &str) -> Result<String, Error> {
@@ -26,54 +33,346 @@ pub mod static_files {
     const HTML_BEGINNING: &str = include_str!("./public/html/beginning.html");
     const HTML_BEGINNING_QUERY_REPLACE: &str = r#"{% search_value %}"#;
 
-    pub fn render_beginning_html(query: &str) -> String {
-        HTML_BEGINNING.replace(
-            &HTML_BEGINNING_QUERY_REPLACE,
-            &html_escape::encode_quoted_attribute(query),
-        )
+    const HTML_BEGINNING_CUSTOM_ENGINES_REPLACE: &str = r#"{% custom_engines_status %}"#;
+
+    pub fn render_beginning_html(
+        query: &str,
+        engines: &[SearchEngine],
+        final_order: bool,
+    ) -> String {
+        // Built-in engines have their logo hardcoded in the HTML
+        let custom_engines_status = engines
+            .iter()
+            .filter(|engine| matches!(engine, SearchEngine::Custom(_)))
+            .map(|engine| {
+                format!(
+                    r#"<div id="search-status-{}"><span>{}</span></div>"#,
+                    engine.html_class(),
+                    html_escape::encode_text(&engine.to_string()),
+                )
+            })
+            .collect::<String>();
+
+        HTML_BEGINNING
+            .replace(
+                HTML_BEGINNING_QUERY_REPLACE,
+                &html_escape::encode_quoted_attribute(query),
+            )
+            .replace(
+                HTML_BEGINNING_CUSTOM_ENGINES_REPLACE,
+                &custom_engines_status,
+            )
+            .replace(
+                "{% final_order %}",
+                match final_order {
+                    true => "checked",
+                    false => "",
+                },
+            )
     }
 
     const FINISHED_CSS: &str = include_str!("./public/css/finished.css");
 
-    pub fn render_finished_css(engine: &str, time: i128) -> String {
+    pub fn render_finished_css(engine: &SearchEngine, time: u128, count: usize) -> String {
         format!(
             "<style>{}</style>",
             FINISHED_CSS
-                .replace("__engine__", engine)
+                .replace("__engine__", &engine.html_class())
                 .replace("{% time %}", &time.to_string())
+                .replace("{% count %}", &count.to_string())
         )
     }
 
-    const HTML_RESULT: &str = include_str!("./public/html/result.html");
+    const FIRST_BYTE_CSS: &str = include_str!("./public/css/first_byte.css");
 
-    pub fn render_result(result: &SearchResult) -> String {
-        HTML_RESULT
-            .replace("{% title %}", &result.title)
-            .replace("{% url %}", &result.url)
-            .replace(
-                "{% url_host %}",
-                Url::parse(&result.url).unwrap().host_str().unwrap(),
-            )
-            .replace("{% description %}", &result.description)
-            .replace("__ID__", &result.get_html_id())
+    pub fn render_first_byte_css(engine: &SearchEngine) -> String {
+        format!(
+            "<style>{}</style>",
+            FIRST_BYTE_CSS.replace("__engine__", &engine.html_class())
+        )
+    }
+
+    const FAILED_CSS: &str = include_str!("./public/css/failed.css");
+
+    pub fn render_failed_css(engine: &SearchEngine, status: &str) -> String {
+        format!(
+            "<style>{}</style>",
+            FAILED_CSS
+                .replace("__engine__", &engine.html_class())
+                .replace("{% status %}", &escape_css_string(status))
+        )
+    }
+
+    const CACHED_CSS: &str = include_str!("./public/css/cached.css");
+
+    pub fn render_cached_css(engine: &SearchEngine) -> String {
+        format!(
+            "<style>{}</style>",
+            CACHED_CSS.replace("__engine__", &engine.html_class())
+        )
+    }
+
+    const HTML_CACHED: &str = include_str!("./public/html/cached.html");
+
+    pub fn render_cached_notice(
+        query: &str,
+        created_at: &DateTime<Utc>,
+        final_order: bool,
+    ) -> String {
+        HTML_CACHED
             .replace(
-                "{% image_url %}",
-                &result.image_url.clone().unwrap_or("".to_string()),
+                "{% date %}",
+                &created_at.format("%d. %B %Y %H:%M").to_string(),
             )
             .replace(
-                "{% date %}",
-                &(match &result.date {
-                    Some(date_info) => date_info.date.format("%d. %B %Y").to_string(),
-                    None => "".to_string(),
-                }),
+                "{% search_value %}",
+                &html_escape::encode_quoted_attribute(query),
             )
+            .replace("{% final_order %}", &final_order.to_string())
+    }
+
+    pub fn render_engine_error_css(engine: &SearchEngine, error: &EngineError) -> String {
+        match error {
+            EngineError::Timeout => render_failed_css(engine, "timed out"),
+            EngineError::Blocked => render_failed_css(engine, "temporarily blocked"),
+            EngineError::RateLimited => render_failed_css(engine, "skipped: rate limited"),
+            _ => render_failed_css(engine, &format!("failed: {}", error)),
+        }
+    }
+
+    const HTML_RESULT: &str = include_str!("./public/html/result.html");
+
+    pub fn render_result(result: &SearchResult, engines: &[SearchEngine]) -> String {
+        render_result_with_id(result, engines, &result.get_html_id())
+    }
+
+    fn render_result_with_id(result: &SearchResult, engines: &[SearchEngine], id: &str) -> String {
+        let search_engines = engines
+            .iter()
+            .map(|engine| {
+                format!(
+                    r#"<li class="{}">{}</li>"#,
+                    engine.html_class(),
+                    html_escape::encode_text(&engine.to_string()),
+                )
+            })
+            .collect::<String>();
+
+        let url_host = Url::parse(&result.url)
+            .ok()
+            .and_then(|url| url.host_str().map(escape_css_string))
+            .unwrap_or_default();
+        let date = match &result.date {
+            Some(date_info) => date_info.date.format("%d. %B %Y").to_string(),
+            None => "".to_string(),
+        };
+
+        fill_template(
+            HTML_RESULT,
+            &[
+                ("{% search_engines %}", &search_engines),
+                ("{% display_url %}", &render_display_url(result)),
+                ("{% extras %}", &render_extras(&result.extras)),
+                ("{% sitelinks %}", &render_sitelinks(&result.extras)),
+                ("{% title %}", &html_escape::encode_text(&result.title)),
+                (
+                    "{% url %}",
+                    &html_escape::encode_double_quoted_attribute(&result.url),
+                ),
+                ("{% url_host %}", &url_host),
+                (
+                    "{% description %}",
+                    &html_escape::encode_text(&result.description),
+                ),
+                ("__ID__", id),
+                (
+                    "{% image_url %}",
+                    &html_escape::encode_double_quoted_attribute(
+                        result.image_url.as_deref().unwrap_or_default(),
+                    ),
+                ),
+                ("{% date %}", &date),
+            ],
+        )
+    }
+
+    /// Replace the placeholders of `template` by their values in a single pass,
+    /// so placeholders within the values (e.g. in the title of a result) are kept as they are
+    fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+        let mut filled = String::with_capacity(template.len());
+        let mut rest = template;
+
+        loop {
+            let next = values
+                .iter()
+                .filter_map(|(placeholder, value)| {
+                    rest.find(placeholder)
+                        .map(|start| (start, placeholder.len(), value))
+                })
+                .min_by_key(|(start, _, _)| *start);
+
+            match next {
+                Some((start, length, value)) => {
+                    filled.push_str(&rest[..start]);
+                    filled.push_str(value);
+                    rest = &rest[start + length..];
+                }
+                None => {
+                    filled.push_str(rest);
+                    return filled;
+                }
+            }
+        }
+    }
+
+    /// The url, or its host followed by the breadcrumbs if there are any
+    fn render_display_url(result: &SearchResult) -> String {
+        let host = Url::parse(&result.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+
+        match (host, result.extras.breadcrumbs.is_empty()) {
+            (Some(host), false) => std::iter::once(host.as_str())
+                .chain(result.extras.breadcrumbs.iter().map(String::as_str))
+                .map(|part| html_escape::encode_text(part).to_string())
+                .collect::<Vec<_>>()
+                .join(" › "),
+            // Shown percent-decoded, as the url is only meant to be read here
+            _ => html_escape::encode_text(&decode_url_lossy(&result.url)).to_string(),
+        }
+    }
+
+    fn format_rating(rating: &Rating) -> String {
+        let value = match rating.value % 10 {
+            0 => format!("{}", rating.value / 10),
+            tenths => format!("{}.{}", rating.value / 10, tenths),
+        };
+
+        match rating.count {
+            Some(count) => format!("★ {}/{} ({})", value, rating.scale, count),
+            None => format!("★ {}/{}", value, rating.scale),
+        }
+    }
+
+    /// "3:05" or "1:02:03"
+    fn format_duration(seconds: u32) -> String {
+        match seconds / 3600 {
+            0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+            hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+        }
+    }
+
+    /// Rating, duration and replies on a single line
+    fn render_extras(extras: &ResultExtras) -> String {
+        let parts = [
+            extras.rating.as_ref().map(format_rating),
+            extras
+                .duration
+                .map(|duration| format!("▶ {}", format_duration(duration))),
+            extras.replies.map(|replies| match replies {
+                1 => "1 reply".to_string(),
+                replies => format!("{} replies", replies),
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|part| format!("<span>{}</span>", html_escape::encode_text(&part)))
+        .collect::<String>();
+
+        match parts.is_empty() {
+            true => String::new(),
+            false => format!(r#"<small class="extras">{}</small>"#, parts),
+        }
+    }
+
+    /// Sitelinks are rendered outside of the result's link, as links can't be nested
+    fn render_sitelinks(extras: &ResultExtras) -> String {
+        if extras.sitelinks.is_empty() {
+            return String::new();
+        }
+
+        let links = extras
+            .sitelinks
+            .iter()
+            .map(|sitelink| {
+                format!(
+                    r#"<li><a href="{}" target="_blank" rel="noopener noreferrer nofollow">{}</a></li>"#,
+                    html_escape::encode_double_quoted_attribute(&sitelink.url),
+                    html_escape::encode_text(&sitelink.title),
+                )
+            })
+            .collect::<String>();
+
+        format!(r#"<ul class="sitelinks">{}</ul>"#, links)
+    }
+
+    const HTML_STATUS: &str = include_str!("./public/html/status.html");
+
+    pub fn render_status_html(engines: &[(SearchEngine, DriftStatus, bool)]) -> String {
+        let rows = engines
+            .iter()
+            .map(|(engine, drift, is_cooling_down)| {
+                let (class, status) = match (drift.likely_broken, is_cooling_down) {
+                    (true, _) => ("broken", "parser likely broken"),
+                    (false, true) => ("", "temporarily blocked"),
+                    (false, false) if drift.responses == 0 => ("", "no responses yet"),
+                    (false, false) => ("", "ok"),
+                };
+
+                format!(
+                    r#"<tr class="{}"><td>{}</td><td>{}</td><td>{:.0}%</td><td>{}</td></tr>"#,
+                    class,
+                    html_escape::encode_text(&engine.to_string()),
+                    drift.responses,
+                    drift.zero_result_rate() * 100.0,
+                    status,
+                )
+            })
+            .collect::<String>();
+
+        HTML_STATUS.replace("{% engines %}", &rows)
+    }
+
+    /// All results in their final order, replacing the ones that were streamed
+    /// as they arrived. The list is nested, so that the streamed results can be
+    /// hidden by a single rule. The copies get their own ids, as the hidden
+    /// results keep theirs.
+    pub fn render_final_results<'a>(
+        results: impl Iterator<Item = &'a FusedResult>,
+        engines: &[SearchEngine],
+    ) -> String {
+        let results = results
+            .map(|fused| {
+                let id = format!("final-{}", fused.result.get_html_id());
+                let engine_visibility = fused
+                    .positions
+                    .keys()
+                    .map(|engine| render_result_engine_visibility(&id, engine))
+                    .collect::<String>();
+
+                format!(
+                    "{}{}",
+                    render_result_with_id(&fused.result, engines, &id),
+                    engine_visibility
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            r#"<li class="final-results"><ul>{}</ul></li><style>#results>ul>.result {{ display: none; }}</style>"#,
+            results
+        )
+    }
+
+    pub fn render_result_order_css(id: &str, order: u32) -> String {
+        format!("<style>#{} {{ order: {}; }}</style>", id, order)
     }
 
     pub fn render_result_engine_visibility(id: &str, engine: &SearchEngine) -> String {
         format!(
             "<style>#{} .search-engines .{} {{ opacity: 1 !important; }}</style>",
             id,
-            engine.to_string().to_lowercase()
+            engine.html_class()
         )
     }
 }
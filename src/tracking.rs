@@ -0,0 +1,357 @@
+// Removal of tracking parameters from result links, such as `utm_source` or `fbclid`.
+//
+// Rules are grouped into providers, which apply to the urls matching their pattern.
+// Besides the built-in rules, providers can be loaded from a local file in the
+// ClearURLs format (https://docs.clearurls.xyz/latest/specs/rules/):
+//
+// {"providers": {"example": {
+//     "urlPattern": "^https?://(?:[a-z0-9-]+\\.)*?example\\.com",
+//     "rules": ["ref_id"], "rawRules": ["/ref=[^/?]*"],
+//     "exceptions": ["^https?://example\\.com/keep"],
+//     "redirections": ["^https?://example\\.com/out\\?to=([^&]*)"]
+// }}}
+pub mod tracking {
+    use std::{collections::BTreeMap, error::Error, fs, path::PathBuf, sync::Arc};
+
+    use regex::{Regex, RegexBuilder};
+    use reqwest::Url;
+    use serde::Deserialize;
+
+    use crate::engines::engine_base::engine_base::SearchResult;
+
+    // Redirections to redirections are followed up to this depth
+    const MAX_REDIRECTION_DEPTH: usize = 4;
+
+    /// Parameters that only identify the campaign or click a visitor came from
+    const GLOBAL_PARAMETERS: [&str; 37] = [
+        "utm_*",
+        "fbclid",
+        "gclid",
+        "gclsrc",
+        "dclid",
+        "gbraid",
+        "wbraid",
+        "msclkid",
+        "mc_cid",
+        "mc_eid",
+        "yclid",
+        "_ga",
+        "_gl",
+        "_hsenc",
+        "_hsmi",
+        "__hssc",
+        "__hstc",
+        "__hsfp",
+        "igshid",
+        "mkt_tok",
+        "oly_anon_id",
+        "oly_enc_id",
+        "vero_id",
+        "rb_clickid",
+        "s_cid",
+        "spm",
+        "scm",
+        "ncid",
+        "cmpid",
+        "trk",
+        "fb_action_ids",
+        "fb_action_types",
+        "fb_ref",
+        "fb_source",
+        "action_object_map",
+        "action_type_map",
+        "action_ref_map",
+    ];
+    /// Parameters that name the referring site, but are part of the page on some sites
+    const REFERRER_PARAMETERS: [&str; 4] = ["ref", "ref_src", "ref_url", "referrer"];
+    /// Sites where `ref` selects a branch or tag
+    const REFERRER_EXCEPTIONS: [&str; 3] = ["github.com", "gitlab.com", "bitbucket.org"];
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(default)]
+    pub struct TrackingConfig {
+        pub enabled: bool,
+        /// Rules in the ClearURLs format, used in addition to the built-in rules
+        pub clearurls_rules: Option<PathBuf>,
+        /// Additional parameter names to remove, `*` matches any characters
+        pub parameters: Vec<String>,
+        /// Domains whose links are never cleaned, including their subdomains
+        pub exceptions: Vec<String>,
+    }
+
+    impl Default for TrackingConfig {
+        fn default() -> Self {
+            Self {
+                enabled: true,
+                clearurls_rules: None,
+                parameters: vec![],
+                exceptions: vec![],
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct ClearUrlsRules {
+        providers: BTreeMap<String, ClearUrlsProvider>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ClearUrlsProvider {
+        url_pattern: String,
+        #[serde(default)]
+        rules: Vec<String>,
+        #[serde(default)]
+        raw_rules: Vec<String>,
+        #[serde(default)]
+        referral_marketing: Vec<String>,
+        #[serde(default)]
+        exceptions: Vec<String>,
+        #[serde(default)]
+        redirections: Vec<String>,
+    }
+
+    /// Rules for the urls matching `url_pattern`
+    #[derive(Debug)]
+    struct Provider {
+        url_pattern: Regex,
+        /// Urls that are left as they are
+        exceptions: Vec<Regex>,
+        /// Names of the query parameters to remove
+        parameters: Vec<Regex>,
+        /// Parts of the url to remove, e.g. `/ref=abc` in paths
+        raw_rules: Vec<Regex>,
+        /// Links through the provider, whose first group is the encoded destination
+        redirections: Vec<Regex>,
+    }
+
+    impl Provider {
+        fn with_parameters(
+            url_pattern: &str,
+            parameters: &[String],
+        ) -> Result<Self, Box<dyn Error>> {
+            Ok(Self {
+                url_pattern: case_insensitive(url_pattern)?,
+                exceptions: vec![],
+                parameters: parameters
+                    .iter()
+                    .map(|parameter| parameter_pattern(&glob_to_regex(parameter)))
+                    .collect::<Result<_, _>>()?,
+                raw_rules: vec![],
+                redirections: vec![],
+            })
+        }
+
+        fn from_clearurls(provider: ClearUrlsProvider) -> Result<Self, Box<dyn Error>> {
+            let compile = |patterns: &[String]| {
+                patterns
+                    .iter()
+                    .map(|pattern| case_insensitive(pattern))
+                    .collect::<Result<Vec<_>, _>>()
+            };
+
+            Ok(Self {
+                url_pattern: case_insensitive(&provider.url_pattern)?,
+                exceptions: compile(&provider.exceptions)?,
+                parameters: provider
+                    .rules
+                    .iter()
+                    .chain(&provider.referral_marketing)
+                    .map(|rule| parameter_pattern(rule))
+                    .collect::<Result<_, _>>()?,
+                raw_rules: compile(&provider.raw_rules)?,
+                redirections: compile(&provider.redirections)?,
+            })
+        }
+
+        fn applies_to(&self, url: &str) -> bool {
+            self.url_pattern.is_match(url)
+                && !self
+                    .exceptions
+                    .iter()
+                    .any(|exception| exception.is_match(url))
+        }
+    }
+
+    /// Removes tracking parameters from urls. Cheap to clone.
+    #[derive(Clone, Debug, Default)]
+    pub struct UrlCleaner {
+        providers: Arc<Vec<Provider>>,
+        /// Domains whose links are never cleaned
+        exceptions: Arc<Vec<String>>,
+    }
+
+    impl UrlCleaner {
+        pub fn from_config(config: &TrackingConfig) -> Result<Self, Box<dyn Error>> {
+            if !config.enabled {
+                return Ok(Self::default());
+            }
+
+            let global = GLOBAL_PARAMETERS
+                .iter()
+                .map(|parameter| parameter.to_string())
+                .chain(config.parameters.iter().cloned())
+                .collect::<Vec<_>>();
+            let mut referrer =
+                Provider::with_parameters(".*", &REFERRER_PARAMETERS.map(str::to_string))?;
+            referrer.exceptions = REFERRER_EXCEPTIONS
+                .iter()
+                .map(|domain| domain_pattern(domain))
+                .collect::<Result<_, _>>()?;
+
+            let mut providers = vec![Provider::with_parameters(".*", &global)?, referrer];
+
+            if let Some(path) = &config.clearurls_rules {
+                let contents = fs::read_to_string(path)
+                    .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+                let rules: ClearUrlsRules = serde_json::from_str(&contents)?;
+
+                for (name, provider) in rules.providers {
+                    providers.push(
+                        Provider::from_clearurls(provider)
+                            .map_err(|error| format!("ClearURLs provider {}: {}", name, error))?,
+                    );
+                }
+            }
+
+            Ok(Self {
+                providers: Arc::new(providers),
+                exceptions: Arc::new(
+                    config
+                        .exceptions
+                        .iter()
+                        .map(|domain| domain.trim_start_matches('.').to_lowercase())
+                        .collect(),
+                ),
+            })
+        }
+
+        /// `url` without the tracking parameters of the providers that apply to it.
+        /// Urls that can't be parsed are returned unchanged.
+        pub fn clean(&self, url: &str) -> String {
+            if self.providers.is_empty() || self.is_exception(url) {
+                return url.to_string();
+            }
+
+            let mut url = url.to_string();
+
+            for _ in 0..MAX_REDIRECTION_DEPTH {
+                let Some(destination) = self.redirection(&url) else {
+                    break;
+                };
+
+                url = destination;
+            }
+
+            let providers = self
+                .providers
+                .iter()
+                .filter(|provider| provider.applies_to(&url))
+                .collect::<Vec<_>>();
+
+            for provider in providers.iter() {
+                for rule in provider.raw_rules.iter() {
+                    url = rule.replace_all(&url, "").into_owned();
+                }
+            }
+
+            let Ok(mut parsed) = Url::parse(&url) else {
+                return url;
+            };
+
+            if let Some(query) = parsed.query() {
+                let kept = query
+                    .split('&')
+                    .filter(|pair| {
+                        let name = pair.split('=').next().unwrap_or_default();
+                        let name = urlencoding::decode(name)
+                            .map(|name| name.into_owned())
+                            .unwrap_or_else(|_| name.to_string());
+
+                        !pair.is_empty()
+                            && !providers.iter().any(|provider| {
+                                provider
+                                    .parameters
+                                    .iter()
+                                    .any(|parameter| parameter.is_match(&name))
+                            })
+                    })
+                    .collect::<Vec<_>>()
+                    .join("&");
+
+                match kept.is_empty() {
+                    true => parsed.set_query(None),
+                    false => parsed.set_query(Some(&kept)),
+                }
+            }
+
+            parsed.to_string()
+        }
+
+        /// The result with its link and the links of its sitelinks cleaned
+        pub fn clean_result(&self, result: &SearchResult) -> SearchResult {
+            let mut result = result.clone();
+            result.url = self.clean(&result.url);
+
+            for sitelink in result.extras.sitelinks.iter_mut() {
+                sitelink.url = self.clean(&sitelink.url);
+            }
+
+            result
+        }
+
+        fn is_exception(&self, url: &str) -> bool {
+            let Some(host) = Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+            else {
+                return false;
+            };
+
+            self.exceptions.iter().any(|domain| {
+                host == *domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|subdomain| subdomain.ends_with('.'))
+            })
+        }
+
+        fn redirection(&self, url: &str) -> Option<String> {
+            self.providers
+                .iter()
+                .filter(|provider| provider.applies_to(url))
+                .flat_map(|provider| provider.redirections.iter())
+                .find_map(|redirection| {
+                    let destination = redirection.captures(url)?.get(1)?.as_str();
+                    let destination = urlencoding::decode(destination).ok()?;
+
+                    Url::parse(&destination).ok().map(|url| url.to_string())
+                })
+        }
+    }
+
+    fn case_insensitive(pattern: &str) -> Result<Regex, Box<dyn Error>> {
+        Ok(RegexBuilder::new(pattern).case_insensitive(true).build()?)
+    }
+
+    /// Matches whole parameter names only
+    fn parameter_pattern(rule: &str) -> Result<Regex, Box<dyn Error>> {
+        case_insensitive(&format!("^(?:{})$", rule))
+    }
+
+    /// `utm_*` into `utm_.*`
+    fn glob_to_regex(glob: &str) -> String {
+        glob.split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*")
+    }
+
+    /// Matches urls on `domain` and its subdomains
+    fn domain_pattern(domain: &str) -> Result<Regex, Box<dyn Error>> {
+        case_insensitive(&format!(
+            r"^[a-z][a-z0-9+.-]*://(?:[^/?#]*\.)?{}(?:[:/?#]|$)",
+            regex::escape(domain)
+        ))
+    }
+}
@@ -0,0 +1,173 @@
+// Tests of the removal of tracking parameters from result links
+pub mod tracking_tests {
+    use std::fs;
+
+    use crate::{
+        engines::{
+            engine_base::engine_base::{SearchEngine, Sitelink},
+            test_fixtures::test_fixtures::{search_result, temporary_directory},
+        },
+        tracking::tracking::{TrackingConfig, UrlCleaner},
+        utils::utils::canonical_url,
+    };
+
+    fn cleaner() -> UrlCleaner {
+        UrlCleaner::from_config(&TrackingConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn removes_tracking_parameters() {
+        let cleaner = cleaner();
+
+        assert_eq!(
+            cleaner.clean("https://example.com/page?utm_source=news&utm_medium=email&id=3"),
+            "https://example.com/page?id=3"
+        );
+        assert_eq!(
+            cleaner.clean("https://example.com/page?fbclid=abc&GCLID=def"),
+            "https://example.com/page"
+        );
+        // Kept parameters keep their encoding and order
+        assert_eq!(
+            cleaner.clean("https://example.com/?q=a%20b&ref=hn&b=1&a=2#top"),
+            "https://example.com/?q=a%20b&b=1&a=2#top"
+        );
+        assert_eq!(
+            cleaner.clean("https://example.com/page?utm_%73ource=x"),
+            "https://example.com/page"
+        );
+        assert_eq!(cleaner.clean("not a url"), "not a url");
+    }
+
+    #[test]
+    fn referrer_exceptions() {
+        let cleaner = cleaner();
+
+        assert_eq!(
+            cleaner.clean("https://github.com/user/repo/blob/main/README.md?ref=v1.0&utm_source=x"),
+            "https://github.com/user/repo/blob/main/README.md?ref=v1.0"
+        );
+        assert_eq!(
+            cleaner.clean("https://gitlab.com/user/repo?ref=main"),
+            "https://gitlab.com/user/repo?ref=main"
+        );
+        assert_eq!(
+            cleaner.clean("https://notgithub.com/page?ref=main"),
+            "https://notgithub.com/page"
+        );
+    }
+
+    #[test]
+    fn configured_parameters_and_exceptions() {
+        let cleaner = UrlCleaner::from_config(&TrackingConfig {
+            parameters: vec!["campaign_*".to_string()],
+            exceptions: vec!["example.org".to_string()],
+            ..TrackingConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            cleaner.clean("https://example.com/?campaign_id=1&campaign=2"),
+            "https://example.com/?campaign=2"
+        );
+        assert_eq!(
+            cleaner.clean("https://docs.example.org/?utm_source=x"),
+            "https://docs.example.org/?utm_source=x"
+        );
+
+        let disabled = UrlCleaner::from_config(&TrackingConfig {
+            enabled: false,
+            ..TrackingConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            disabled.clean("https://example.com/?utm_source=x"),
+            "https://example.com/?utm_source=x"
+        );
+    }
+
+    #[test]
+    fn clearurls_rules() {
+        let directory = temporary_directory("clearurls");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("rules.json");
+        fs::write(
+            &path,
+            r#"{"providers": {
+                "shop": {
+                    "urlPattern": "^https?://(?:[a-z0-9-]+\\.)*?shop\\.example",
+                    "completeProvider": false,
+                    "rules": ["tag", "pd_rd_[a-z]+"],
+                    "rawRules": ["/ref=[^/?]*"],
+                    "referralMarketing": ["affiliate"],
+                    "exceptions": ["^https?://shop\\.example/account"],
+                    "redirections": ["^https?://shop\\.example/out\\?to=([^&]*)"],
+                    "forceRedirection": false
+                }
+            }}"#,
+        )
+        .unwrap();
+
+        let cleaner = UrlCleaner::from_config(&TrackingConfig {
+            clearurls_rules: Some(path.clone()),
+            ..TrackingConfig::default()
+        });
+        fs::remove_dir_all(&directory).unwrap();
+        let cleaner = cleaner.unwrap();
+
+        assert_eq!(
+            cleaner
+                .clean("https://www.shop.example/item/ref=sr_1?tag=x&pd_rd_w=y&affiliate=z&id=4"),
+            "https://www.shop.example/item?id=4"
+        );
+        // Rules only apply to their provider's urls
+        assert_eq!(
+            cleaner.clean("https://example.com/?tag=rust"),
+            "https://example.com/?tag=rust"
+        );
+        assert_eq!(
+            cleaner.clean("https://shop.example/account?tag=x"),
+            "https://shop.example/account?tag=x"
+        );
+        assert_eq!(
+            cleaner
+                .clean("https://shop.example/out?to=https%3A%2F%2Fexample.com%2F%3Futm_source%3Dx"),
+            "https://example.com/"
+        );
+
+        let invalid = UrlCleaner::from_config(&TrackingConfig {
+            clearurls_rules: Some(temporary_directory("missing-clearurls").join("rules.json")),
+            ..TrackingConfig::default()
+        });
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn cleaned_duplicates_merge() {
+        let cleaner = cleaner();
+
+        let mut tracked = search_result(
+            SearchEngine::Brave,
+            "https://www.example.com/page?utm_source=bing",
+            1,
+        );
+        tracked.extras.sitelinks.push(Sitelink {
+            title: "Docs".to_string(),
+            url: "https://example.com/docs?fbclid=1".to_string(),
+        });
+        let tracked = cleaner.clean_result(&tracked);
+        let plain = cleaner.clean_result(&search_result(
+            SearchEngine::Brave,
+            "https://example.com/page",
+            1,
+        ));
+
+        assert_eq!(tracked.extras.sitelinks[0].url, "https://example.com/docs");
+        assert_eq!(tracked.canonical_url(), plain.canonical_url());
+        assert_ne!(
+            canonical_url("https://example.com/page?utm_source=bing"),
+            plain.canonical_url()
+        );
+    }
+}